@@ -1,12 +1,14 @@
 use serde::{Deserialize, Serialize};
 use gloo_net::http::Request;
 use serde_json::json;
-use crate::auth::get_stored_token;
+use crate::auth::get_session_token;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShortenRequest {
     pub url: String,
     pub custom_alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_style: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -16,6 +18,12 @@ pub struct ShortenResponse {
     pub original_url: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionResponse {
+    pub token: String,
+    pub expires_at: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct UrlInfo {
     pub id: String,
@@ -23,16 +31,65 @@ pub struct UrlInfo {
     pub original_url: String,
     pub created_at: String,
     pub clicks: i64,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub favicon_url: Option<String>,
+    #[serde(default)]
+    pub last_status: Option<i64>,
+    #[serde(default)]
+    pub broken_since: Option<String>,
 }
 
+// Mirrors `AnalyticsResponse` in the worker's models; labels are null when unknown
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnalyticsData {
     pub total_clicks: i64,
-    pub countries: Vec<serde_json::Value>,
-    pub devices: Vec<serde_json::Value>,
-    pub browsers: Vec<serde_json::Value>,
-    pub timeline: Vec<serde_json::Value>,
-    pub referrers: Vec<serde_json::Value>,
+    pub countries: Vec<CountryCount>,
+    pub devices: Vec<DeviceCount>,
+    pub browsers: Vec<BrowserCount>,
+    pub timeline: Vec<DateCount>,
+    pub referrers: Vec<ReferrerCount>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CountryCount {
+    pub country: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceCount {
+    pub device_type: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BrowserCount {
+    pub browser: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DateCount {
+    pub date: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReferrerCount {
+    pub referrer: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AliasAvailability {
+    pub alias: String,
+    pub available: bool,
+    pub reason: Option<String>,
+    pub suggestions: Vec<String>,
 }
 
 // Helper to get API URL (assumes same origin or configured)
@@ -40,16 +97,43 @@ pub struct AnalyticsData {
 // For production, it might be different, but let's assume relative for now or env var.
 const API_BASE: &str = "https://s.blatik-short.workers.dev"; // Hardcoded for now based on existing config
 
-pub async fn shorten_url(url: String, custom_alias: Option<String>, user_id: Option<String>) -> Result<ShortenResponse, String> {
-    let body = ShortenRequest { url, custom_alias };
-    
-    let mut req = Request::post(&format!("{}/api/shorten", API_BASE));
-        
-    if let Some(uid) = user_id {
-        req = req.header("X-User-ID", &uid);
+// Exchange a Google ID token for a session token
+pub async fn create_session(id_token: String) -> Result<SessionResponse, String> {
+    let resp = Request::post(&format!("{}/api/v1/auth/session", API_BASE))
+        .json(&json!({ "id_token": id_token }))
+        .map_err(|e| e.to_string())?
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !resp.ok() {
+        let err_json: serde_json::Value = resp.json().await.unwrap_or(json!({"error": "Unknown error"}));
+        return Err(err_json["error"].as_str().unwrap_or("Unknown error").to_string());
     }
+
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+// Revoke the current session on the server
+pub async fn end_session() -> Result<(), String> {
+    let Some(token) = get_session_token() else {
+        return Ok(());
+    };
+    Request::delete(&format!("{}/api/v1/auth/session", API_BASE))
+        .header("Authorization", &format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub async fn shorten_url(url: String, custom_alias: Option<String>, readable_code: bool) -> Result<ShortenResponse, String> {
+    let code_style = readable_code.then(|| "words".to_string());
+    let body = ShortenRequest { url, custom_alias, code_style };
+    
+    let mut req = Request::post(&format!("{}/api/v1/shorten", API_BASE));
     
-    if let Some(token) = get_stored_token() {
+    if let Some(token) = get_session_token() {
         req = req.header("Authorization", &format!("Bearer {}", token));
     }
     
@@ -64,11 +148,10 @@ pub async fn shorten_url(url: String, custom_alias: Option<String>, user_id: Opt
     resp.json().await.map_err(|e| e.to_string())
 }
 
-pub async fn get_user_urls(user_id: String) -> Result<Vec<UrlInfo>, String> {
-    let mut req = Request::get(&format!("{}/api/urls", API_BASE))
-        .header("X-User-ID", &user_id);
+pub async fn get_user_urls() -> Result<Vec<UrlInfo>, String> {
+    let mut req = Request::get(&format!("{}/api/v1/urls", API_BASE));
         
-    if let Some(token) = get_stored_token() {
+    if let Some(token) = get_session_token() {
         req = req.header("Authorization", &format!("Bearer {}", token));
     }
         
@@ -82,7 +165,7 @@ pub async fn get_user_urls(user_id: String) -> Result<Vec<UrlInfo>, String> {
 }
 
 pub async fn get_analytics(code: String) -> Result<AnalyticsData, String> {
-    let resp = Request::get(&format!("{}/api/analytics/{}", API_BASE, code))
+    let resp = Request::get(&format!("{}/api/v1/analytics/{}", API_BASE, code))
         .send()
         .await
         .map_err(|e| e.to_string())?;
@@ -93,3 +176,18 @@ pub async fn get_analytics(code: String) -> Result<AnalyticsData, String> {
     
     resp.json().await.map_err(|e| e.to_string())
 }
+
+pub async fn check_alias(alias: String) -> Result<AliasAvailability, String> {
+    // Aliases may contain any letter, so they must be encoded to fit in the path
+    let alias = js_sys::encode_uri_component(&alias);
+    let resp = Request::get(&format!("{}/api/v1/alias/{}/available", API_BASE, alias))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !resp.ok() {
+        return Err("Failed to check alias".to_string());
+    }
+
+    resp.json().await.map_err(|e| e.to_string())
+}
@@ -39,6 +39,25 @@ pub fn set_stored_token(token: Option<String>) {
     }
 }
 
+// The API's own session token, issued in exchange for the Google token
+pub fn get_session_token() -> Option<String> {
+    window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|ls| ls.get_item("session_token").ok().flatten())
+}
+
+pub fn set_session_token(token: Option<String>) {
+    if let Some(w) = window() {
+        if let Ok(Some(ls)) = w.local_storage() {
+            if let Some(t) = token {
+                let _ = ls.set_item("session_token", &t);
+            } else {
+                let _ = ls.remove_item("session_token");
+            }
+        }
+    }
+}
+
 // Decode JWT (simplified, no verification on client side)
 pub fn decode_jwt(token: &str) -> Option<UserProfile> {
     use base64::{Engine as _, engine::general_purpose};
@@ -74,14 +93,21 @@ pub fn handle_credential_response(response: JsValue) {
     
     if let Ok(credential) = js_sys::Reflect::get(&response_obj, &credential_key) {
         if let Some(token) = credential.as_string() {
-            web_sys::console::log_1(&format!("Got token: {}", token).into());
-            set_stored_token(Some(token.clone()));
-            
-            // Reload page to refresh state (simplest way for now)
-            // Or dispatch a custom event
-            if let Some(w) = window() {
-                let _ = w.location().reload();
-            }
+            // The Google token is only kept for the profile; the API needs a session token
+            spawn_local(async move {
+                match crate::api::create_session(token.clone()).await {
+                    Ok(session) => {
+                        set_stored_token(Some(token));
+                        set_session_token(Some(session.token));
+                    }
+                    Err(e) => web_sys::console::error_1(&format!("Sign-in failed: {}", e).into()),
+                }
+
+                // Reload page to refresh state (simplest way for now)
+                if let Some(w) = window() {
+                    let _ = w.location().reload();
+                }
+            });
         }
     }
 }
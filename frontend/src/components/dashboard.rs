@@ -3,15 +3,9 @@ use crate::api::{get_user_urls, UrlInfo};
 
 #[component]
 pub fn Dashboard(refresh_signal: ReadSignal<i32>) -> impl IntoView {
-    // TODO: Get real user ID
-    let user_id = "anonymous".to_string(); 
-    
     let urls_resource = create_resource(
         move || refresh_signal.get(),
-        move |_| {
-            let uid = user_id.clone();
-            async move { get_user_urls(uid).await }
-        }
+        move |_| async move { get_user_urls().await }
     );
 
     view! {
@@ -53,7 +47,23 @@ pub fn Dashboard(refresh_signal: ReadSignal<i32>) -> impl IntoView {
                                                 view! {
                                                     <tr>
                                                         <td><a href=format!("https://{}", short_link) target="_blank" class="short-link">{short_code}</a></td>
-                                                        <td><span class="original-link" title=url.original_url.clone()>{url.original_url}</span></td>
+                                                        <td>
+                                                            <div class="link-preview" title=url.description.clone().unwrap_or_else(|| url.original_url.clone())>
+                                                                {url.favicon_url.map(|src| view! { <img class="link-favicon" src=src alt="" /> })}
+                                                                <div class="link-text">
+                                                                    {url.title.map(|t| view! { <span class="link-title">{t}</span> })}
+                                                                    <span class="original-link">{url.original_url}</span>
+                                                                    {url.broken_since.map(|since| {
+                                                                        let status = url.last_status.map(|s| s.to_string()).unwrap_or_else(|| "unreachable".to_string());
+                                                                        view! {
+                                                                            <span class="broken-badge" title=format!("Failing since {} (last status: {})", since, status)>
+                                                                                "⚠️ Broken"
+                                                                            </span>
+                                                                        }
+                                                                    })}
+                                                                </div>
+                                                            </div>
+                                                        </td>
                                                         <td>{url.clicks}</td>
                                                         <td>{url.created_at}</td> 
                                                         <td>
@@ -1,15 +1,27 @@
 use leptos::*;
-use crate::api::shorten_url;
+use crate::api::{check_alias, shorten_url};
 
 #[component]
 pub fn Shortener(on_success: Action<String, ()>) -> impl IntoView {
     let (url, set_url) = create_signal(String::new());
     let (custom_alias, set_custom_alias) = create_signal(String::new());
     let (use_alias, set_use_alias) = create_signal(false);
+    let (readable_code, set_readable_code) = create_signal(false);
     let (loading, set_loading) = create_signal(false);
     let (error, set_error) = create_signal(Option::<String>::None);
     let (result, set_result) = create_signal(Option::<String>::None);
 
+    // Check the alias as the user types
+    let availability = create_resource(
+        move || (use_alias.get(), custom_alias.get()),
+        |(enabled, alias)| async move {
+            if !enabled || alias.chars().count() < 2 {
+                return None;
+            }
+            check_alias(alias).await.ok()
+        }
+    );
+
     let shorten_action = create_action(move |_| {
         let url = url.get();
         let alias = if use_alias.get() && !custom_alias.get().is_empty() {
@@ -17,16 +29,14 @@ pub fn Shortener(on_success: Action<String, ()>) -> impl IntoView {
         } else {
             None
         };
-        
-        // TODO: Get real user ID
-        let user_id = Some("anonymous".to_string()); 
+        let readable = readable_code.get();
 
         async move {
             set_loading.set(true);
             set_error.set(None);
             set_result.set(None);
             
-            match shorten_url(url, alias, user_id).await {
+            match shorten_url(url, alias, readable).await {
                 Ok(resp) => {
                     set_result.set(Some(resp.short_url));
                     set_loading.set(false);
@@ -70,14 +80,46 @@ pub fn Shortener(on_success: Action<String, ()>) -> impl IntoView {
                         />
                         <span>"Custom alias"</span>
                     </label>
+                    <label class="checkbox-label" style=move || if use_alias.get() { "display: none" } else { "" }>
+                        <input 
+                            type="checkbox" 
+                            prop:checked=readable_code
+                            on:change=move |ev| set_readable_code.set(event_target_checked(&ev))
+                        />
+                        <span>"Readable code (e.g. brave-otter-42)"</span>
+                    </label>
                     <input 
                         type="text" 
                         placeholder="my-custom-link" 
-                        pattern="[a-zA-Z0-9_-]{3,20}"
+                        maxlength="64"
                         style=move || if use_alias.get() { "display: block" } else { "display: none" }
                         prop:value=custom_alias
                         on:input=move |ev| set_custom_alias.set(event_target_value(&ev))
                     />
+                    {move || availability.get().flatten().map(|a| {
+                        if a.available {
+                            view! { <div class="alias-status alias-available">"✅ Available"</div> }.into_view()
+                        } else {
+                            view! {
+                                <div class="alias-status alias-taken">
+                                    <span>{format!("❌ {}", a.reason.unwrap_or_default())}</span>
+                                    {(!a.suggestions.is_empty()).then(|| view! {
+                                        <div class="alias-suggestions">
+                                            "Try: "
+                                            {a.suggestions.into_iter().map(|suggestion| {
+                                                let value = suggestion.clone();
+                                                view! {
+                                                    <button type="button" class="alias-suggestion" on:click=move |_| set_custom_alias.set(value.clone())>
+                                                        {suggestion}
+                                                    </button>
+                                                }
+                                            }).collect_view()}
+                                        </div>
+                                    })}
+                                </div>
+                            }.into_view()
+                        }
+                    })}
                 </div>
             </form>
 
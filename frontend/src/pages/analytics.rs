@@ -29,20 +29,15 @@ pub fn Analytics() -> impl IntoView {
             <Suspense fallback=move || view! { <p>"Loading analytics..."</p> }>
                 {move || match analytics_resource.get() {
                     Some(Ok(data)) => {
-                        // Helper to convert JSON data to Vec<(String, f64)>
-                        let to_chart_data = |json_vec: &Vec<serde_json::Value>, label_key: &str| {
-                            json_vec.iter().map(|v| {
-                                (
-                                    v[label_key].as_str().unwrap_or("Unknown").to_string(),
-                                    v["count"].as_f64().unwrap_or(0.0)
-                                )
-                            }).collect::<Vec<_>>()
+                        // Chart points as (label, count)
+                        let point = |label: &Option<String>, count: i64| {
+                            (label.clone().unwrap_or_else(|| "Unknown".to_string()), count as f64)
                         };
 
-                        let countries_data = to_chart_data(&data.countries, "country");
-                        let devices_data = to_chart_data(&data.devices, "device_type");
-                        let browsers_data = to_chart_data(&data.browsers, "browser");
-                        let referrers_data = to_chart_data(&data.referrers, "referrer");
+                        let countries_data: Vec<_> = data.countries.iter().map(|c| point(&c.country, c.count)).collect();
+                        let devices_data: Vec<_> = data.devices.iter().map(|d| point(&d.device_type, d.count)).collect();
+                        let browsers_data: Vec<_> = data.browsers.iter().map(|b| point(&b.browser, b.count)).collect();
+                        let referrers_data: Vec<_> = data.referrers.iter().map(|r| point(&r.referrer, r.count)).collect();
 
                         view! {
                             <div class="analytics-dashboard">
@@ -30,8 +30,12 @@ pub fn Home() -> impl IntoView {
                                     <img id="userAvatar" src=u.picture alt="Avatar" class="user-avatar" />
                                     <span id="userName">{u.name}</span>
                                     <button id="signOutBtn" class="btn-outline-sm" on:click=move |_| {
-                                        crate::auth::set_stored_token(None);
-                                        let _ = window().location().reload();
+                                        spawn_local(async move {
+                                            let _ = crate::api::end_session().await;
+                                            crate::auth::set_stored_token(None);
+                                            crate::auth::set_session_token(None);
+                                            let _ = window().location().reload();
+                                        });
                                     }>"Sign Out"</button>
                                 </div>
                             }.into_view(),
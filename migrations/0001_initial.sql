@@ -0,0 +1,67 @@
+-- Migration: Initial schema, as set up by schema.sql and the migration_add_*.sql scripts
+-- Every statement is IF NOT EXISTS, so databases created before versioned migrations adopt it unchanged
+
+-- URLs table
+CREATE TABLE IF NOT EXISTS urls (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    short_code TEXT UNIQUE NOT NULL,
+    original_url TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    clicks INTEGER DEFAULT 0,
+    title TEXT,
+    description TEXT,
+    favicon_url TEXT,
+    last_status INTEGER,
+    last_checked_at TIMESTAMP,
+    consecutive_failures INTEGER DEFAULT 0,
+    broken_since TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_urls_short_code ON urls(short_code);
+CREATE INDEX IF NOT EXISTS idx_urls_user_id ON urls(user_id);
+-- idx_urls_last_checked_at is created by 0002, since schema.sql databases lack the column
+
+-- Detailed clicks table for analytics
+CREATE TABLE IF NOT EXISTS clicks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    short_code TEXT NOT NULL,
+    clicked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    country TEXT,
+    city TEXT,
+    device_type TEXT,
+    browser TEXT,
+    os TEXT,
+    referrer TEXT,
+    ip_hash TEXT,
+    FOREIGN KEY (short_code) REFERENCES urls(short_code) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_clicks_short_code ON clicks(short_code);
+CREATE INDEX IF NOT EXISTS idx_clicks_timestamp ON clicks(clicked_at);
+CREATE INDEX IF NOT EXISTS idx_clicks_country ON clicks(country);
+
+-- Blocked destinations (checked at creation and on every redirect)
+CREATE TABLE IF NOT EXISTS blocklist (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('domain', 'pattern')),
+    value TEXT NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (kind, value)
+);
+
+-- Named counters (e.g. the short code sequence when CODE_STRATEGY = "sequence")
+CREATE TABLE IF NOT EXISTS sequences (
+    name TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+
+-- Results of the scheduled KV/D1 reconciliation job
+CREATE TABLE IF NOT EXISTS reconcile_runs (
+    id TEXT PRIMARY KEY,
+    ran_at TIMESTAMP NOT NULL,
+    report TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_reconcile_runs_ran_at ON reconcile_runs(ran_at);
@@ -0,0 +1,73 @@
+-- Migration: TEXT (UUID) ids, link expiry in urls, no cascade from urls to clicks
+-- Tables are rebuilt by copying rows, so existing links and analytics are kept.
+-- Scraped metadata and health-check state are copied too, when the ad-hoc ALTER scripts added them.
+
+-- Rebuild clicks first: its foreign key would cascade-delete every click when the old urls table is dropped.
+-- Links delete their own clicks, so the new table has no foreign key.
+CREATE TABLE clicks_new (
+    id TEXT PRIMARY KEY,
+    short_code TEXT NOT NULL,
+    clicked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    country TEXT,
+    city TEXT,
+    device_type TEXT,
+    browser TEXT,
+    os TEXT,
+    referrer TEXT,
+    ip_hash TEXT
+);
+
+INSERT INTO clicks_new (id, short_code, clicked_at, country, city, device_type, browser, os, referrer, ip_hash)
+SELECT CAST(id AS TEXT), short_code, clicked_at, country, city, device_type, browser, os, referrer, ip_hash FROM clicks;
+
+DROP TABLE clicks;
+ALTER TABLE clicks_new RENAME TO clicks;
+
+CREATE INDEX idx_clicks_short_code ON clicks(short_code);
+CREATE INDEX idx_clicks_timestamp ON clicks(clicked_at);
+CREATE INDEX idx_clicks_country ON clicks(country);
+
+CREATE TABLE urls_new (
+    id TEXT PRIMARY KEY,
+    short_code TEXT UNIQUE NOT NULL,
+    original_url TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP,
+    clicks INTEGER DEFAULT 0,
+    title TEXT,
+    description TEXT,
+    favicon_url TEXT,
+    last_status INTEGER,
+    last_checked_at TIMESTAMP,
+    consecutive_failures INTEGER DEFAULT 0,
+    broken_since TIMESTAMP
+);
+
+-- The columns from migration_add_url_metadata.sql and migration_add_health_checks.sql may be missing.
+-- Each is read through a subquery over urls: SQLite resolves a name the subquery's table lacks against
+-- the outer query, so a missing column falls back to the `legacy` default instead of failing.
+INSERT INTO urls_new (
+    id, short_code, original_url, user_id, created_at, clicks,
+    title, description, favicon_url, last_status, last_checked_at, consecutive_failures, broken_since
+)
+SELECT
+    CAST(u.id AS TEXT), u.short_code, u.original_url, u.user_id, u.created_at, u.clicks,
+    (SELECT title FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT description FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT favicon_url FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT last_status FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT last_checked_at FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT consecutive_failures FROM urls AS s WHERE s.rowid = u.rowid),
+    (SELECT broken_since FROM urls AS s WHERE s.rowid = u.rowid)
+FROM urls AS u, (
+    SELECT NULL AS title, NULL AS description, NULL AS favicon_url,
+           NULL AS last_status, NULL AS last_checked_at, 0 AS consecutive_failures, NULL AS broken_since
+) AS legacy;
+
+DROP TABLE urls;
+ALTER TABLE urls_new RENAME TO urls;
+
+CREATE INDEX idx_urls_short_code ON urls(short_code);
+CREATE INDEX idx_urls_user_id ON urls(user_id);
+CREATE INDEX idx_urls_last_checked_at ON urls(last_checked_at);
@@ -0,0 +1,10 @@
+-- Migration: users, recorded on sign-in. The subscription tier gates API keys and alias lengths.
+
+CREATE TABLE IF NOT EXISTS users (
+    -- Google account id (`sub`)
+    id TEXT PRIMARY KEY,
+    email TEXT,
+    subscription_tier TEXT NOT NULL DEFAULT 'free' CHECK (subscription_tier IN ('free', 'pro', 'business')),
+    created_at TIMESTAMP NOT NULL,
+    stripe_customer_id TEXT
+);
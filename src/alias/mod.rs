@@ -0,0 +1,281 @@
+use std::fmt;
+
+use percent_encoding::percent_decode_str;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::models::SubscriptionTier;
+
+/// Paths the service uses itself or may use later; never valid as short codes
+pub const RESERVED_ALIASES: &[&str] = &[
+    "about", "account", "admin", "analytics", "api", "app", "assets", "auth", "billing", "blog",
+    "callback", "dashboard", "docs", "favicon", "help", "home", "index", "login", "logout",
+    "null", "oauth", "pricing", "privacy", "robots", "settings", "signin", "signup", "sitemap",
+    "static", "status", "support", "terms", "undefined", "www",
+];
+
+/// Built-in offensive words; extend with the `ALIAS_DENYLIST` var
+const OFFENSIVE_WORDS: &[&str] = &[
+    "asshole", "bastard", "bitch", "cock", "cunt", "dick", "fag", "fuck", "motherfucker",
+    "nazi", "nigga", "nigger", "porn", "pussy", "rape", "retard", "shit", "slut", "twat", "whore",
+];
+
+/// Compose `alias` to NFC, so `café` typed either way is the same alias
+pub fn compose(alias: &str) -> String {
+    alias.nfc().collect()
+}
+
+/// The short code in a raw request path segment: percent-decoded, then composed
+pub fn decode_path_segment(segment: &str) -> String {
+    compose(&percent_decode_str(segment).decode_utf8_lossy())
+}
+
+/// Whether `alias` is a reserved word (case-insensitive)
+pub fn is_reserved_alias(alias: &str) -> bool {
+    let alias = alias.to_lowercase();
+    RESERVED_ALIASES.contains(&alias.as_str())
+}
+
+/// Decides which aliases are too offensive to hand out. Shared by the server's request threads.
+pub trait Denylist: Send + Sync {
+    fn is_denied(&self, alias: &str) -> bool;
+}
+
+/// Matches whole words of an alias, so `peacock-farm` passes but `fuck-you` and `sh1t` don't
+pub struct WordDenylist {
+    words: Vec<String>,
+}
+
+impl WordDenylist {
+    pub fn builtin() -> Self {
+        Self {
+            words: OFFENSIVE_WORDS.iter().map(|w| unleet(w)).collect(),
+        }
+    }
+
+    /// Add comma-separated words on top of the current list
+    pub fn with_words(mut self, extra: &str) -> Self {
+        self.words.extend(
+            extra
+                .split(',')
+                .map(|w| unleet(w.trim()))
+                .filter(|w| !w.is_empty()),
+        );
+        self
+    }
+}
+
+impl Denylist for WordDenylist {
+    fn is_denied(&self, alias: &str) -> bool {
+        let normalized = unleet(alias);
+        // Check each word on its own and the whole alias with separators removed
+        let collapsed: String = normalized.split(['-', '_']).collect();
+        normalized
+            .split(['-', '_'])
+            .chain(std::iter::once(collapsed.as_str()))
+            .any(|token| self.words.iter().any(|w| token == w || token.trim_end_matches('s') == w))
+    }
+}
+
+// Lowercase and undo common character substitutions (f4ck, sh1t, $hit)
+fn unleet(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            other => other,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AliasError {
+    TooShort(usize),
+    TooLong(usize),
+    InvalidCharacters,
+    Reserved,
+    Offensive,
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AliasError::TooShort(min) => write!(f, "Custom alias must be at least {} characters", min),
+            AliasError::TooLong(max) => write!(f, "Custom alias must be at most {} characters", max),
+            AliasError::InvalidCharacters => write!(f, "Custom alias may only contain letters, digits, hyphens or underscores"),
+            AliasError::Reserved => write!(f, "This alias is reserved. Please choose another one."),
+            AliasError::Offensive => write!(f, "This alias is not allowed. Please choose another one."),
+        }
+    }
+}
+
+/// Rules for user-chosen aliases
+pub struct AliasPolicy {
+    /// Allow any Unicode letter or digit instead of just `[A-Za-z0-9]`
+    pub allow_unicode: bool,
+    /// Treat `Promo` and `promo` as the same alias
+    pub case_insensitive: bool,
+    pub denylist: Box<dyn Denylist>,
+}
+
+impl Default for AliasPolicy {
+    fn default() -> Self {
+        Self {
+            allow_unicode: false,
+            case_insensitive: true,
+            denylist: Box::new(WordDenylist::builtin()),
+        }
+    }
+}
+
+impl AliasPolicy {
+    /// Build the policy from `ALIAS_ALLOW_UNICODE`, `ALIAS_CASE_INSENSITIVE` and `ALIAS_DENYLIST`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let flag = |name: &str, default: bool| {
+            var(name).map(|v| v.trim().eq_ignore_ascii_case("true")).unwrap_or(default)
+        };
+        let defaults = Self::default();
+
+        Self {
+            allow_unicode: flag("ALIAS_ALLOW_UNICODE", defaults.allow_unicode),
+            case_insensitive: flag("ALIAS_CASE_INSENSITIVE", defaults.case_insensitive),
+            denylist: Box::new(WordDenylist::builtin().with_words(&var("ALIAS_DENYLIST").unwrap_or_default())),
+        }
+    }
+
+    /// Check `alias` against the policy for a user on `tier`
+    pub fn validate(&self, alias: &str, tier: &SubscriptionTier) -> Result<(), AliasError> {
+        let (min, max) = tier.alias_length();
+        let len = alias.chars().count();
+        if len < min {
+            return Err(AliasError::TooShort(min));
+        }
+        if len > max {
+            return Err(AliasError::TooLong(max));
+        }
+
+        let valid_char = |c: char| {
+            c == '-' || c == '_' || if self.allow_unicode { c.is_alphanumeric() } else { c.is_ascii_alphanumeric() }
+        };
+        if !alias.chars().all(valid_char) {
+            return Err(AliasError::InvalidCharacters);
+        }
+
+        if is_reserved_alias(alias) {
+            return Err(AliasError::Reserved);
+        }
+        if self.denylist.is_denied(alias) {
+            return Err(AliasError::Offensive);
+        }
+
+        Ok(())
+    }
+
+    /// The form an alias is stored and looked up under
+    pub fn normalize(&self, alias: &str) -> String {
+        let alias = compose(alias);
+        if self.case_insensitive {
+            alias.to_lowercase()
+        } else {
+            alias
+        }
+    }
+}
+
+/// Alternatives to offer when `alias` is taken, most similar first.
+/// Callers still need to check availability and the policy for each one.
+pub fn alias_candidates(alias: &str, today: chrono::NaiveDate) -> Vec<String> {
+    use chrono::Datelike;
+
+    let alias = alias.trim_matches(|c| c == '-' || c == '_');
+    let month = today.format("%b").to_string().to_lowercase();
+    let mut candidates = vec![
+        format!("{}-{}", alias, 2),
+        format!("{}{}", alias, 2),
+        format!("{}-{}", alias, today.year()),
+        format!("{}-{}{}", alias, month, today.day()),
+        format!("my-{}", alias),
+        format!("{}-link", alias),
+        format!("get-{}", alias),
+    ];
+    candidates.extend((3..=9).map(|n| format!("{}-{}", alias, n)));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_reserved_alias() {
+        assert!(is_reserved_alias("api"));
+        assert!(is_reserved_alias("Admin"));
+        assert!(!is_reserved_alias("my-link"));
+        assert!(!is_reserved_alias("apis"));
+    }
+
+    #[test]
+    fn test_validate_alias() {
+        let policy = AliasPolicy::default();
+        let free = SubscriptionTier::Free;
+
+        assert!(policy.validate("my-link", &free).is_ok());
+        assert!(policy.validate("test_123", &free).is_ok());
+        assert_eq!(policy.validate("ab", &free), Err(AliasError::TooShort(3)));
+        assert_eq!(policy.validate("this-is-way-too-long-alias", &free), Err(AliasError::TooLong(20)));
+        assert_eq!(policy.validate("invalid@alias", &free), Err(AliasError::InvalidCharacters));
+        assert_eq!(policy.validate("admin", &free), Err(AliasError::Reserved));
+
+        // Longer aliases on paid tiers
+        assert!(policy.validate("this-is-way-too-long-alias", &SubscriptionTier::Pro).is_ok());
+    }
+
+    #[test]
+    fn test_unicode_and_case_rules() {
+        let ascii = AliasPolicy::default();
+        assert_eq!(ascii.validate("café", &SubscriptionTier::Free), Err(AliasError::InvalidCharacters));
+        assert_eq!(ascii.normalize("Promo"), "promo");
+
+        let unicode = AliasPolicy {
+            allow_unicode: true,
+            case_insensitive: false,
+            ..AliasPolicy::default()
+        };
+        assert!(unicode.validate("café", &SubscriptionTier::Free).is_ok());
+        assert_eq!(unicode.normalize("Promo"), "Promo");
+    }
+
+    #[test]
+    fn test_decode_path_segment() {
+        assert_eq!(decode_path_segment("promo"), "promo");
+        assert_eq!(decode_path_segment("caf%C3%A9"), "caf\u{e9}");
+        assert_eq!(decode_path_segment("cafe%CC%81"), "caf\u{e9}");
+        assert_eq!(decode_path_segment("%D0%BA%D0%B8%D1%97%D0%B2"), "київ");
+    }
+
+    #[test]
+    fn test_denylist() {
+        let policy = AliasPolicy::from_vars(|name| (name == "ALIAS_DENYLIST").then(|| "spam, Scam".to_string()));
+        let free = SubscriptionTier::Free;
+
+        assert_eq!(policy.validate("fuck-you", &free), Err(AliasError::Offensive));
+        assert_eq!(policy.validate("Sh1t", &free), Err(AliasError::Offensive));
+        assert_eq!(policy.validate("free_scams", &free), Err(AliasError::Offensive));
+        assert!(policy.validate("peacock-farm", &free).is_ok());
+        assert!(policy.validate("scunthorpe", &free).is_ok());
+    }
+
+    #[test]
+    fn test_alias_candidates() {
+        let today = chrono::NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let candidates = alias_candidates("promo", today);
+
+        assert_eq!(&candidates[..4], ["promo-2", "promo2", "promo-2026", "promo-oct19"]);
+        assert!(candidates.contains(&"my-promo".to_string()));
+        assert!(!candidates.contains(&"promo".to_string()));
+    }
+}
@@ -0,0 +1,1193 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::alias::{alias_candidates, compose, is_reserved_alias, AliasPolicy};
+use crate::auth::{self, AuthError, GoogleVerifier, KeySource, SessionClaims};
+use crate::blocklist::{find_match, warning_page, BlockEntry, NewBlockEntry};
+use crate::codegen::{self, Alphabet, CodeGenerator, CodeStrategy, CodeStyle, HashCodes, RandomCodes, SequenceCodes, WordCodes};
+use crate::cors::CorsPolicy;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{
+    AliasAvailability, AnalyticsResponse, ApiKey, ApiKeyInfo, ApiKeyRequest, BrowserCount, Click, CountryCount, CreatedApiKey, DateCount,
+    DeviceCount, ReferrerCount, Session, SessionRequest, SessionResponse, ShortenRequest, ShortenResponse, SubscriptionTier,
+    UpdateLinkRequest, Url, User,
+};
+use crate::openapi;
+use crate::resolve::{own_hosts, resolve_destination, RedirectSource};
+use crate::scraper::{scrape, PageFetcher};
+use crate::store::{ApiKeyStore, BlocklistStore, ClickStats, ClickStore, LinkStore, MaintenanceStore, SessionStore, StoreError, StoreResult, UserStore};
+use crate::utils::{current_timestamp, generate_api_key, generate_uuid, is_valid_url};
+
+/// Incoming request, already stripped of everything platform-specific
+#[derive(Debug, Clone, Default)]
+pub struct ApiRequest {
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    /// Caller identity, resolved by the platform layer
+    pub user_id: Option<String>,
+}
+
+impl ApiRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_lowercase(), value.to_string());
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn with_user(mut self, user_id: Option<String>) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// Response to be converted into the platform's own type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl ApiResponse {
+    fn new(status: u16, content_type: &str, body: String) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body,
+        }
+    }
+
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        // Our response types always serialize
+        Self::new(200, "application/json", serde_json::to_string(value).unwrap_or_default())
+    }
+
+    pub fn html(body: String) -> Self {
+        Self::new(200, "text/html;charset=UTF-8", body)
+    }
+
+    pub fn no_content() -> Self {
+        Self {
+            status: 204,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        Self {
+            status: 302,
+            headers: vec![("Location".to_string(), location.to_string())],
+            body: String::new(),
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Mark the response as coming from a deprecated route replaced by `successor`
+    pub fn deprecated(mut self, successor: &str) -> Self {
+        for (name, value) in deprecation_headers(successor) {
+            self.headers.push((name.to_string(), value));
+        }
+        self
+    }
+}
+
+const ROBOTS_TXT: &str = "User-agent: *\nDisallow: /api/\n";
+
+const FAVICON_SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100"><text y=".9em" font-size="90">🔗</text></svg>"#;
+
+const HOME_PAGE: &str = "https://blatik.github.io/shortlink";
+
+/// Prefix of the current API version. Breaking changes go to a new version.
+pub const API_V1: &str = "/api/v1";
+
+/// For an unversioned `/api/...` path, the `/api/v1` path it is a deprecated alias of
+pub fn versioned_successor(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/api/")?;
+    if rest == "v1" || rest.starts_with("v1/") {
+        return None;
+    }
+    Some(format!("{}/{}", API_V1, rest))
+}
+
+/// Headers sent with every response from a deprecated route (RFC 9745, RFC 8288)
+pub fn deprecation_headers(successor: &str) -> [(&'static str, String); 2] {
+    [
+        ("Deprecation", "true".to_string()),
+        ("Link", format!("<{}>; rel=\"successor-version\"", successor)),
+    ]
+}
+
+impl From<ApiError> for ApiResponse {
+    fn from(e: ApiError) -> Self {
+        ApiResponse::json(&e.to_response()).with_status(e.status())
+    }
+}
+
+// A week, unless `SESSION_TTL_HOURS` says otherwise
+const DEFAULT_SESSION_TTL_HOURS: i64 = 24 * 7;
+
+/// Settings read from the deployment's environment variables
+pub struct Config {
+    pub base_url: String,
+    /// Hosts serving our short links, see `resolve::own_hosts`
+    pub own_hosts: Vec<String>,
+    pub expand_shorteners: bool,
+    pub code_strategy: CodeStrategy,
+    pub alphabet: Alphabet,
+    pub code_length: usize,
+    pub alias_policy: AliasPolicy,
+    pub cors: CorsPolicy,
+    /// OAuth client IDs Google ID tokens must be issued for
+    pub google_client_ids: Vec<String>,
+    /// Signs session tokens; sessions are refused while it is empty
+    pub jwt_secret: String,
+    pub session_ttl_hours: i64,
+    /// Required in `X-Admin-Token` by the admin endpoints; they are refused while it is empty
+    pub admin_token: String,
+}
+
+impl Config {
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let base_url = var("BASE_URL").unwrap_or_default();
+
+        Self {
+            own_hosts: own_hosts(&base_url, var("SHORT_DOMAINS").as_deref()),
+            base_url,
+            expand_shorteners: var("EXPAND_SHORTENERS").is_some_and(|v| v == "true"),
+            code_strategy: var("CODE_STRATEGY")
+                .and_then(|v| CodeStrategy::parse(&v))
+                .unwrap_or(CodeStrategy::Random),
+            alphabet: var("CODE_ALPHABET")
+                .and_then(|v| Alphabet::parse(&v))
+                .unwrap_or_default(),
+            code_length: var("CODE_LENGTH")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(codegen::DEFAULT_CODE_LENGTH)
+                .clamp(2, 32),
+            alias_policy: AliasPolicy::from_vars(&var),
+            cors: CorsPolicy::from_vars(&var),
+            google_client_ids: var("GOOGLE_CLIENT_ID")
+                .unwrap_or_default()
+                .split(',')
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect(),
+            jwt_secret: var("JWT_SECRET").unwrap_or_default(),
+            session_ttl_hours: var("SESSION_TTL_HOURS")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|hours| *hours > 0)
+                .unwrap_or(DEFAULT_SESSION_TTL_HOURS),
+            admin_token: var("ADMIN_TOKEN").unwrap_or_default(),
+        }
+    }
+}
+
+/// The shortener's request handling, independent of where it runs
+pub struct App<S, R> {
+    pub store: S,
+    /// Used to follow destinations that are themselves short links
+    pub redirects: R,
+    pub config: Config,
+}
+
+impl<S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore, R: RedirectSource> App<S, R> {
+    /// Create a short link. Also returns the created link, so the caller can
+    /// scrape its metadata in the background.
+    pub async fn shorten(&self, req: &ApiRequest) -> ApiResult<(ApiResponse, Url)> {
+        // Parse request body
+        let body: ShortenRequest = serde_json::from_str(&req.body).map_err(|_| ApiError::InvalidBody)?;
+
+        // Validate URL
+        if !is_valid_url(&body.url) {
+            return Err(ApiError::InvalidUrl);
+        }
+
+        // Follow links to our own domains (and optionally other shorteners) to the real destination
+        let config = &self.config;
+        let destination = resolve_destination(&self.redirects, &config.own_hosts, config.expand_shorteners, &body.url)
+            .await
+            .map_err(ApiError::InvalidDestination)?;
+
+        // Reject known malicious destinations
+        if find_match(&self.store.blocklist().await?, &destination).is_some() {
+            return Err(ApiError::BlockedDestination);
+        }
+
+        // Default to anonymous if no valid ID found
+        let user_id = req.user_id.clone().or(Some("anonymous".to_string()));
+
+        // Create URL object; the short code is filled in once it has been reserved
+        let mut url = Url {
+            id: generate_uuid(),
+            short_code: String::new(),
+            original_url: destination,
+            user_id: user_id.clone(),
+            created_at: current_timestamp(),
+            expires_at: None,
+            clicks: 0,
+            title: None,
+            description: None,
+            favicon_url: None,
+            last_status: None,
+            last_checked_at: None,
+            consecutive_failures: 0,
+            broken_since: None,
+        };
+
+        // Reserve the short code first. The store's reservation is the authoritative
+        // check, so two concurrent requests can never both claim a code.
+        if let Some(alias) = body.custom_alias.as_deref().map(compose) {
+            // Alias length limits depend on the caller's tier
+            let tier = self.caller_tier(req).await?;
+            let policy = &config.alias_policy;
+            policy.validate(&alias, &tier)?;
+            url.short_code = policy.normalize(&alias);
+
+            // Published links are checked too, since KV still holds links created before D1 was authoritative
+            let taken = self.store.get_link(&url.short_code).await?.is_some() || !self.store.reserve_link(&url).await?;
+            if taken {
+                return Err(ApiError::AliasTaken {
+                    suggestions: self.suggest_aliases(&url.short_code, &tier).await?,
+                });
+            }
+        } else {
+            // Generate unique short code; every strategy gives up after a bounded number of attempts
+            let generator: Box<dyn CodeGenerator> = match body.code_style {
+                Some(CodeStyle::Words) => Box::new(WordCodes::default()),
+                _ => self.code_generator(&format!("{}\n{}", user_id.as_deref().unwrap_or_default(), url.original_url)).await?,
+            };
+            let hashed = config.code_strategy == CodeStrategy::Hash && body.code_style != Some(CodeStyle::Words);
+            let mut attempt = 0;
+
+            loop {
+                url.short_code = generator.candidate(attempt).ok_or(ApiError::CodeUnavailable)?;
+                attempt += 1;
+
+                if is_reserved_alias(&url.short_code) {
+                    continue;
+                }
+                if let Some(existing) = self.store.get_link(&url.short_code).await? {
+                    // Hash codes come from the owner and destination, so shortening the same
+                    // destination again finds the earlier link; return it instead of a duplicate
+                    let same_link = existing.user_id == url.user_id && existing.original_url == url.original_url;
+                    if hashed && same_link && !is_expired(&existing) {
+                        let response = self.shorten_response(&existing);
+                        return Ok((response, existing));
+                    }
+                    continue;
+                }
+                if self.store.reserve_link(&url).await? {
+                    break;
+                }
+            }
+        }
+
+        // Publish only once the code is ours. If that fails, release the reservation
+        // so we never leave a dashboard entry that doesn't redirect.
+        if let Err(e) = self.store.publish_link(&url).await {
+            let _ = self.store.release_link(&url).await;
+            return Err(e.into());
+        }
+
+        Ok((self.shorten_response(&url), url))
+    }
+
+    fn shorten_response(&self, url: &Url) -> ApiResponse {
+        ApiResponse::json(&ShortenResponse {
+            short_url: format!("{}/{}", self.config.base_url, url.short_code),
+            short_code: url.short_code.clone(),
+            original_url: url.original_url.clone(),
+        })
+    }
+
+    // Pick the generator configured by `CODE_STRATEGY`, `CODE_ALPHABET` and `CODE_LENGTH`
+    async fn code_generator(&self, hash_input: &str) -> StoreResult<Box<dyn CodeGenerator>> {
+        let alphabet = self.config.alphabet.clone();
+        let length = self.config.code_length;
+
+        Ok(match self.config.code_strategy {
+            CodeStrategy::Random => Box::new(RandomCodes::new(alphabet, length)),
+            CodeStrategy::Hash => Box::new(HashCodes::new(hash_input.to_string(), alphabet, length)),
+            CodeStrategy::Sequence => {
+                // Reserve a block of counter values so retries don't need another round trip
+                let first = self.store.reserve_sequence(codegen::SEQUENCE_BLOCK).await?;
+                Box::new(SequenceCodes {
+                    alphabet,
+                    first,
+                    count: codegen::SEQUENCE_BLOCK,
+                    min_length: length,
+                })
+            }
+        })
+    }
+
+    /// Exchange a Google ID token for a first-party session token
+    pub async fn create_session<K: KeySource>(&self, google: &GoogleVerifier<'_, K>, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let body: SessionRequest = serde_json::from_str(&req.body).map_err(|_| ApiError::InvalidBody)?;
+        let claims = google
+            .verify(&body.id_token, Utc::now().timestamp())
+            .await
+            .map_err(|e| ApiError::InvalidToken(e.to_string()))?;
+
+        self.store
+            .record_user(&User {
+                id: claims.sub.clone(),
+                email: claims.email,
+                subscription_tier: SubscriptionTier::Free,
+                created_at: current_timestamp(),
+                stripe_customer_id: None,
+            })
+            .await?;
+        self.start_session(&claims.sub).await
+    }
+
+    /// Issue a session for a user whose identity is already verified
+    pub async fn start_session(&self, user_id: &str) -> ApiResult<ApiResponse> {
+        let now = Utc::now();
+        let expires = now + Duration::hours(self.config.session_ttl_hours);
+        let session = Session {
+            id: generate_uuid(),
+            user_id: user_id.to_string(),
+            created_at: now.to_rfc3339(),
+            expires_at: expires.to_rfc3339(),
+            revoked_at: None,
+        };
+        let claims = SessionClaims {
+            sub: session.user_id.clone(),
+            sid: session.id.clone(),
+            iat: now.timestamp(),
+            exp: expires.timestamp(),
+        };
+        let token = auth::sign_session(&self.config.jwt_secret, &claims).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        self.store.create_session(&session).await?;
+        Ok(ApiResponse::json(&SessionResponse {
+            token,
+            expires_at: session.expires_at,
+        }))
+    }
+
+    /// The user behind the session token or API key in `Authorization`, or `None` without one
+    pub async fn authenticate(&self, req: &ApiRequest) -> ApiResult<Option<String>> {
+        let Some(token) = bearer_token(req)? else {
+            return Ok(None);
+        };
+        if token.starts_with(auth::API_KEY_PREFIX) {
+            return self.authenticate_key(token).await.map(Some);
+        }
+        let claims = self.session_claims(token)?;
+
+        match self.store.get_session(&claims.sid).await? {
+            Some(session) if session.revoked_at.is_none() && session.user_id == claims.sub => Ok(Some(claims.sub)),
+            Some(_) => Err(ApiError::InvalidToken("Session has ended".to_string())),
+            None => Err(ApiError::InvalidToken("Unknown session".to_string())),
+        }
+    }
+
+    /// Log out: revoke the session whose token is in `Authorization`
+    pub async fn end_session(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let token = bearer_token(req)?.ok_or(ApiError::Unauthenticated)?;
+        let claims = self.session_claims(token)?;
+
+        self.store.revoke_session(&claims.sid, &current_timestamp()).await?;
+        Ok(ApiResponse::no_content())
+    }
+
+    // The owner of an API key, recording when it was last used
+    async fn authenticate_key(&self, key: &str) -> ApiResult<String> {
+        // `last_used_at` is only rewritten when older than this, to spare a write per request
+        const LAST_USED_RESOLUTION: i64 = 60;
+
+        let api_key = match self.store.find_api_key(&auth::hash_api_key(key)).await? {
+            Some(api_key) if api_key.revoked_at.is_none() => api_key,
+            _ => return Err(ApiError::InvalidToken("Unknown or revoked API key".to_string())),
+        };
+        // Keys stop working if the owner moves to a tier without API access
+        if !self.tier(&api_key.user_id).await?.has_api_access() {
+            return Err(ApiError::ApiAccessRequired);
+        }
+
+        let now = Utc::now();
+        let stale = api_key
+            .last_used_at
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .is_none_or(|t| now.signed_duration_since(t).num_seconds() >= LAST_USED_RESOLUTION);
+        if stale {
+            self.store.touch_api_key(&api_key.id, &now.to_rfc3339()).await?;
+        }
+        Ok(api_key.user_id)
+    }
+
+    /// The user's subscription tier; unknown users are on the free tier
+    async fn tier(&self, user_id: &str) -> ApiResult<SubscriptionTier> {
+        let user = self.store.get_user(user_id).await?;
+        Ok(user.map_or(SubscriptionTier::Free, |u| u.subscription_tier))
+    }
+
+    // Anonymous callers get the free tier's limits
+    async fn caller_tier(&self, req: &ApiRequest) -> ApiResult<SubscriptionTier> {
+        match &req.user_id {
+            Some(user_id) => self.tier(user_id).await,
+            None => Ok(SubscriptionTier::Free),
+        }
+    }
+
+    fn session_claims(&self, token: &str) -> ApiResult<SessionClaims> {
+        auth::verify_session(&self.config.jwt_secret, token, Utc::now().timestamp()).map_err(|e| match e {
+            AuthError::MissingSecret => ApiError::Internal(e.to_string()),
+            e => ApiError::InvalidToken(e.to_string()),
+        })
+    }
+
+    /// Create an API key for the caller. The key is only ever returned here.
+    pub async fn create_api_key(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let user_id = req.user_id.as_ref().ok_or(ApiError::Unauthenticated)?;
+        if !self.tier(user_id).await?.has_api_access() {
+            return Err(ApiError::ApiAccessRequired);
+        }
+        let name = key_name(&req.body)?;
+
+        let key = generate_api_key();
+        let api_key = ApiKey {
+            id: generate_uuid(),
+            user_id: user_id.clone(),
+            name,
+            key_hash: auth::hash_api_key(&key),
+            prefix: auth::api_key_prefix(&key),
+            created_at: current_timestamp(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        self.store.create_api_key(&api_key).await?;
+        Ok(ApiResponse::json(&CreatedApiKey {
+            api_key: ApiKeyInfo::from(&api_key),
+            key,
+        }))
+    }
+
+    pub async fn list_api_keys(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let user_id = req.user_id.as_ref().ok_or(ApiError::Unauthenticated)?;
+        let keys = self.store.list_api_keys(user_id).await?;
+
+        Ok(ApiResponse::json(&keys.iter().map(ApiKeyInfo::from).collect::<Vec<_>>()))
+    }
+
+    pub async fn rename_api_key(&self, id: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let name = key_name(&req.body)?;
+        let mut api_key = self.owned_api_key(id, req).await?;
+
+        self.store.rename_api_key(&api_key.id, &name).await?;
+        api_key.name = name;
+        Ok(ApiResponse::json(&ApiKeyInfo::from(&api_key)))
+    }
+
+    /// Revoke one of the caller's keys; requests using it fail from then on
+    pub async fn revoke_api_key(&self, id: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let api_key = self.owned_api_key(id, req).await?;
+
+        self.store.revoke_api_key(&api_key.id, &current_timestamp()).await?;
+        Ok(ApiResponse::no_content())
+    }
+
+    // One of the caller's unrevoked keys
+    async fn owned_api_key(&self, id: &str, req: &ApiRequest) -> ApiResult<ApiKey> {
+        let user_id = req.user_id.as_ref().ok_or(ApiError::Unauthenticated)?;
+
+        let keys = self.store.list_api_keys(user_id).await?;
+        keys.into_iter().find(|k| k.id == id).ok_or(ApiError::ApiKeyNotFound)
+    }
+
+    pub async fn list_links(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let user_id = req.user_id.as_ref().ok_or(ApiError::Unauthenticated)?;
+
+        Ok(ApiResponse::json(&self.store.list_links(user_id, 50).await?))
+    }
+
+    /// Change the destination or expiry of one of the caller's links
+    pub async fn update_link(&self, short_code: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let body: UpdateLinkRequest = serde_json::from_str(&req.body).map_err(|_| ApiError::InvalidBody)?;
+        let mut url = self.owned_link(short_code, req).await?;
+
+        if let Some(destination) = body.url {
+            if !is_valid_url(&destination) {
+                return Err(ApiError::InvalidUrl);
+            }
+            let config = &self.config;
+            url.original_url = resolve_destination(&self.redirects, &config.own_hosts, config.expand_shorteners, &destination)
+                .await
+                .map_err(ApiError::InvalidDestination)?;
+            if find_match(&self.store.blocklist().await?, &url.original_url).is_some() {
+                return Err(ApiError::BlockedDestination);
+            }
+        }
+
+        if let Some(expires_at) = body.expires_at {
+            url.expires_at = match expires_at.trim() {
+                "" => None,
+                value if chrono::DateTime::parse_from_rfc3339(value).is_ok() => Some(value.to_string()),
+                _ => return Err(ApiError::InvalidExpiry),
+            };
+        }
+
+        self.store.update_link(&url).await?;
+        Ok(ApiResponse::json(&url))
+    }
+
+    pub async fn delete_link(&self, short_code: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let url = self.owned_link(short_code, req).await?;
+
+        self.store.delete_link(&url).await?;
+        Ok(ApiResponse::no_content())
+    }
+
+    // The caller's published link
+    async fn owned_link(&self, short_code: &str, req: &ApiRequest) -> ApiResult<Url> {
+        let user_id = req.user_id.as_ref().ok_or(ApiError::Unauthenticated)?;
+
+        // Someone else's link is reported as missing so codes can't be probed for owners
+        match self.store.get_link(short_code).await? {
+            Some(url) if url.user_id.as_ref() == Some(user_id) => Ok(url),
+            _ => Err(ApiError::LinkNotFound),
+        }
+    }
+
+    pub async fn analytics(&self, short_code: &str) -> ApiResult<ApiResponse> {
+        let stats = self.store.click_stats(short_code).await?;
+
+        Ok(ApiResponse::json(&AnalyticsResponse::from(stats)))
+    }
+
+    /// The OpenAPI document, with this deployment's base URL as the server
+    pub fn openapi(&self) -> ApiResponse {
+        ApiResponse::json(&openapi::document(&self.config.base_url))
+    }
+
+    pub fn docs(&self) -> ApiResponse {
+        ApiResponse::html(openapi::DOCS_PAGE.to_string())
+    }
+
+    // Served directly so browsers and crawlers never hit the short link lookup
+    pub fn robots(&self) -> ApiResponse {
+        ApiResponse::new(200, "text/plain;charset=UTF-8", ROBOTS_TXT.to_string())
+    }
+
+    pub fn favicon(&self) -> ApiResponse {
+        let mut response = ApiResponse::new(200, "image/svg+xml", FAVICON_SVG.to_string());
+        response.headers.push(("Cache-Control".to_string(), "public, max-age=86400".to_string()));
+        response
+    }
+
+    /// The bare domain sends visitors to the web app
+    pub fn home(&self) -> ApiResponse {
+        ApiResponse::redirect(HOME_PAGE)
+    }
+
+    /// Whether the caller could create `alias`, judged by their tier's limits
+    pub async fn alias_available(&self, alias: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        let tier = self.caller_tier(req).await?;
+        let policy = &self.config.alias_policy;
+        if let Err(e) = policy.validate(alias, &tier) {
+            return Ok(ApiResponse::json(&AliasAvailability {
+                alias: alias.to_string(),
+                available: false,
+                reason: Some(e.to_string()),
+                suggestions: Vec::new(),
+            }));
+        }
+
+        let alias = policy.normalize(alias);
+        if self.store.get_link(&alias).await?.is_none() {
+            return Ok(ApiResponse::json(&AliasAvailability {
+                alias,
+                available: true,
+                reason: None,
+                suggestions: Vec::new(),
+            }));
+        }
+
+        let suggestions = self.suggest_aliases(&alias, &tier).await?;
+        Ok(ApiResponse::json(&AliasAvailability {
+            alias,
+            available: false,
+            reason: Some("Custom alias already taken".to_string()),
+            suggestions,
+        }))
+    }
+
+    // Up to five free variants of a taken alias that also pass the alias policy
+    async fn suggest_aliases(&self, alias: &str, tier: &SubscriptionTier) -> StoreResult<Vec<String>> {
+        const MAX_SUGGESTIONS: usize = 5;
+
+        let policy = &self.config.alias_policy;
+        let mut suggestions = Vec::new();
+        for candidate in alias_candidates(alias, chrono::Utc::now().date_naive()) {
+            if suggestions.len() >= MAX_SUGGESTIONS {
+                break;
+            }
+            if policy.validate(&candidate, tier).is_err() {
+                continue;
+            }
+            let candidate = policy.normalize(&candidate);
+            if self.store.get_link(&candidate).await?.is_none() {
+                suggestions.push(candidate);
+            }
+        }
+
+        Ok(suggestions)
+    }
+
+    pub async fn redirect(&self, short_code: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        // System paths are never short links, so don't look them up or count them as clicks
+        if is_reserved_alias(short_code) {
+            return Err(ApiError::NotFound);
+        }
+
+        // Look the link up, falling back to the folded alias for case-insensitive lookups
+        let mut link = self.store.get_link(short_code).await?;
+        if link.is_none() {
+            let normalized = self.config.alias_policy.normalize(short_code);
+            if normalized != short_code {
+                link = self.store.get_link(&normalized).await?;
+            }
+        }
+        let url = link.ok_or(ApiError::LinkNotFound)?;
+        let short_code = &url.short_code;
+
+        // Destinations blocked after the link was created stop working immediately
+        if find_match(&self.store.blocklist().await?, &url.original_url).is_some() {
+            return Ok(ApiResponse::html(warning_page(short_code)).with_status(403));
+        }
+
+        if is_expired(&url) {
+            return Err(ApiError::LinkExpired);
+        }
+
+        // Extract analytics data from headers
+        let header = |name: &str, default: &str| req.header(name).unwrap_or(default).to_string();
+        let user_agent = header("User-Agent", "Unknown");
+
+        // Parse User-Agent for device/browser/OS
+        let (device_type, browser, os) = parse_user_agent(&user_agent);
+
+        // Hash IP for privacy (Cloudflare provides CF-Connecting-IP)
+        let ip = header("CF-Connecting-IP", "0.0.0.0");
+        let ip_hash = format!("{:x}", md5::compute(ip.as_bytes()));
+
+        // Analytics failures must never block the redirect
+        let click = Click {
+            id: generate_uuid(),
+            short_code: short_code.to_string(),
+            clicked_at: current_timestamp(),
+            country: Some(header("CF-IPCountry", "Unknown")),
+            city: Some(header("CF-IPCity", "Unknown")),
+            device_type: Some(device_type),
+            browser: Some(browser),
+            os: Some(os),
+            referrer: Some(header("Referer", "Direct")),
+            ip_hash: Some(ip_hash),
+        };
+        let _ = self.store.record_click(&click).await;
+
+        // Perform redirect
+        match url::Url::parse(&url.original_url) {
+            Ok(destination) => Ok(ApiResponse::redirect(destination.as_str())),
+            Err(e) => Err(ApiError::Internal(format!("stored URL of {} is invalid: {}", short_code, e))),
+        }
+    }
+
+    /// Admin endpoints need the `ADMIN_TOKEN` secret in `X-Admin-Token`
+    pub fn require_admin(&self, req: &ApiRequest) -> ApiResult<()> {
+        let expected = &self.config.admin_token;
+        let provided = req.header("X-Admin-Token").unwrap_or_default();
+
+        // Digests are compared in constant time, so timing reveals neither the token nor its length
+        let matches: bool = Sha256::digest(provided).ct_eq(&Sha256::digest(expected)).into();
+        if expected.is_empty() || !matches {
+            return Err(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+
+    pub async fn list_blocklist(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        self.require_admin(req)?;
+
+        Ok(ApiResponse::json(&self.store.blocklist().await?))
+    }
+
+    /// Block a domain or URL pattern. Blocking it again returns the existing entry.
+    pub async fn add_block_entry(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        self.require_admin(req)?;
+        let body = serde_json::from_str::<NewBlockEntry>(&req.body)
+            .ok()
+            .and_then(NewBlockEntry::normalized)
+            .ok_or(ApiError::InvalidBody)?;
+
+        let entry = BlockEntry {
+            id: generate_uuid(),
+            kind: body.kind,
+            value: body.value,
+            reason: body.reason,
+            created_at: current_timestamp(),
+        };
+        if self.store.add_block_entry(&entry).await? {
+            return Ok(ApiResponse::json(&entry).with_status(201));
+        }
+
+        let existing = self.store.blocklist().await?.into_iter().find(|e| e.kind == entry.kind && e.value == entry.value);
+        Ok(ApiResponse::json(&existing.unwrap_or(entry)))
+    }
+
+    pub async fn remove_block_entry(&self, id: &str, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        self.require_admin(req)?;
+
+        if !self.store.remove_block_entry(id).await? {
+            return Err(ApiError::NotFound);
+        }
+        Ok(ApiResponse::no_content())
+    }
+
+    /// Most recent reconciliation reports, newest first
+    pub async fn reconcile_reports(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        self.require_admin(req)?;
+
+        Ok(ApiResponse::json(&self.store.reconcile_reports(10).await?))
+    }
+
+    /// Compare the database with the migrations and columns this build expects; 409 when it is behind
+    pub async fn schema_report(&self, req: &ApiRequest) -> ApiResult<ApiResponse> {
+        self.require_admin(req)?;
+
+        let report = self.store.schema_report().await?;
+        let status = if report.is_current() { 200 } else { 409 };
+        Ok(ApiResponse::json(&report).with_status(status))
+    }
+}
+
+// Whether the link has an expiry that has passed
+fn is_expired(url: &Url) -> bool {
+    url.expires_at
+        .as_deref()
+        .and_then(|expires_at| chrono::DateTime::parse_from_rfc3339(expires_at).ok())
+        .is_some_and(|expires| expires < chrono::Utc::now())
+}
+
+// The trimmed `name` of an `ApiKeyRequest` body
+fn key_name(body: &str) -> ApiResult<String> {
+    let body: ApiKeyRequest = serde_json::from_str(body).map_err(|_| ApiError::InvalidBody)?;
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(ApiError::InvalidKeyName);
+    }
+    Ok(name.to_string())
+}
+
+// The token of an `Authorization: Bearer` header
+fn bearer_token(req: &ApiRequest) -> ApiResult<Option<&str>> {
+    match req.header("Authorization") {
+        Some(value) => value
+            .strip_prefix("Bearer ")
+            .map(|token| Some(token.trim()))
+            .ok_or_else(|| ApiError::InvalidToken("Expected a Bearer token".to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Fetch the destination page and save its metadata
+pub async fn store_page_metadata<S: LinkStore, F: PageFetcher>(store: &S, fetcher: &F, url: Url) -> StoreResult<()> {
+    let meta = scrape(fetcher, &url.original_url).await.map_err(StoreError)?;
+
+    store.update_metadata(&url.short_code, &meta).await
+}
+
+// Shape grouped counts like the original D1 rows, e.g. `{"country": "UA", "count": 3}`
+impl From<ClickStats> for AnalyticsResponse {
+    fn from(stats: ClickStats) -> Self {
+        Self {
+            total_clicks: stats.total_clicks,
+            countries: stats.countries.into_iter().map(|g| CountryCount { country: g.label, count: g.count }).collect(),
+            devices: stats.devices.into_iter().map(|g| DeviceCount { device_type: g.label, count: g.count }).collect(),
+            browsers: stats.browsers.into_iter().map(|g| BrowserCount { browser: g.label, count: g.count }).collect(),
+            timeline: stats
+                .timeline
+                .into_iter()
+                .map(|g| DateCount { date: g.label.unwrap_or_default(), count: g.count })
+                .collect(),
+            referrers: stats.referrers.into_iter().map(|g| ReferrerCount { referrer: g.label, count: g.count }).collect(),
+        }
+    }
+}
+
+pub fn parse_user_agent(ua: &str) -> (String, String, String) {
+    let ua_lower = ua.to_lowercase();
+    
+    // Detect device type
+    let device = if ua_lower.contains("mobile") || ua_lower.contains("android") || ua_lower.contains("iphone") {
+        "Mobile"
+    } else if ua_lower.contains("tablet") || ua_lower.contains("ipad") {
+        "Tablet"
+    } else {
+        "Desktop"
+    }.to_string();
+    
+    // Detect browser
+    let browser = if ua_lower.contains("edg") {
+        "Edge"
+    } else if ua_lower.contains("chrome") {
+        "Chrome"
+    } else if ua_lower.contains("safari") && !ua_lower.contains("chrome") {
+        "Safari"
+    } else if ua_lower.contains("firefox") {
+        "Firefox"
+    } else if ua_lower.contains("opera") || ua_lower.contains("opr") {
+        "Opera"
+    } else {
+        "Other"
+    }.to_string();
+    
+    // Detect OS
+    let os = if ua_lower.contains("windows") {
+        "Windows"
+    } else if ua_lower.contains("mac") {
+        "macOS"
+    } else if ua_lower.contains("linux") {
+        "Linux"
+    } else if ua_lower.contains("android") {
+        "Android"
+    } else if ua_lower.contains("ios") || ua_lower.contains("iphone") || ua_lower.contains("ipad") {
+        "iOS"
+    } else {
+        "Other"
+    }.to_string();
+    
+    (device, browser, os)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::block_on;
+    use crate::store::MemoryStore;
+
+    // Destinations never point at other short links in these tests
+    struct NoRedirects;
+
+    impl RedirectSource for NoRedirects {
+        async fn short_link(&self, _code: &str) -> Option<String> {
+            None
+        }
+
+        async fn follow(&self, _url: &str) -> Option<String> {
+            None
+        }
+    }
+
+    fn app() -> App<MemoryStore, NoRedirects> {
+        App {
+            store: MemoryStore::new(),
+            redirects: NoRedirects,
+            config: Config::from_vars(|name| match name {
+                "BASE_URL" => Some("https://sho.rt".to_string()),
+                "JWT_SECRET" => Some("test-secret".to_string()),
+                "ADMIN_TOKEN" => Some("admin-secret".to_string()),
+                _ => None,
+            }),
+        }
+    }
+
+    // The response a client would see, errors included
+    fn respond(result: ApiResult<ApiResponse>) -> ApiResponse {
+        result.unwrap_or_else(ApiResponse::from)
+    }
+
+    fn shorten(app: &App<MemoryStore, NoRedirects>, body: serde_json::Value) -> serde_json::Value {
+        let req = ApiRequest::new().with_body(body.to_string()).with_user(Some("alice".to_string()));
+        let response = respond(block_on(app.shorten(&req)).map(|(response, _)| response));
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_shorten_redirect_and_analytics() {
+        let app = app();
+        let req = ApiRequest::new().with_body(r#"{"url": "https://example.com/page"}"#);
+        let (response, created) = block_on(app.shorten(&req)).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["short_url"], format!("https://sho.rt/{}", created.short_code));
+        assert_eq!(created.user_id.as_deref(), Some("anonymous"));
+
+        let click = ApiRequest::new()
+            .with_header("CF-IPCountry", "UA")
+            .with_header("User-Agent", "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0) Mobile Safari");
+        let response = block_on(app.redirect(&created.short_code, &click)).unwrap();
+        assert_eq!(response.status, 302);
+        assert_eq!(response.header("location"), Some("https://example.com/page"));
+
+        let response = block_on(app.analytics(&created.short_code)).unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(stats["total_clicks"], 1);
+        assert_eq!(stats["countries"][0], serde_json::json!({ "country": "UA", "count": 1 }));
+        assert_eq!(stats["devices"][0]["device_type"], "Mobile");
+        assert_eq!(stats["referrers"][0]["referrer"], "Direct");
+        // Clients read the same body back as the typed response
+        let typed: AnalyticsResponse = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(typed.timeline.len(), 1);
+        assert_eq!(app.store.row(&created.short_code).unwrap().clicks, 1);
+    }
+
+    #[test]
+    fn test_custom_alias() {
+        let app = app();
+        let body = shorten(&app, serde_json::json!({ "url": "https://example.com", "custom_alias": "Promo" }));
+        assert_eq!(body["short_code"], "promo");
+
+        let taken = shorten(&app, serde_json::json!({ "url": "https://example.org", "custom_alias": "promo" }));
+        assert_eq!(taken["code"], "ALIAS_TAKEN");
+        assert_eq!(taken["error"], "Custom alias already taken");
+        assert!(!taken["suggestions"].as_array().unwrap().is_empty());
+
+        let req = ApiRequest::new().with_body(r#"{"url": "https://example.org", "custom_alias": "promo"}"#);
+        assert_eq!(respond(block_on(app.shorten(&req)).map(|(r, _)| r)).status, 409);
+        let req = ApiRequest::new().with_body(r#"{"url": "https://example.org", "custom_alias": "a"}"#);
+        assert_eq!(block_on(app.shorten(&req)).unwrap_err().code(), "INVALID_ALIAS");
+
+        // Lookups fold case like the alias did
+        let response = block_on(app.redirect("PROMO", &ApiRequest::new())).unwrap();
+        assert_eq!(response.status, 302);
+
+        // Longer aliases once the caller is on a paid tier
+        let long = serde_json::json!({ "url": "https://example.com", "custom_alias": "summer-sale-2030-landing" });
+        assert_eq!(shorten(&app, long.clone())["code"], "INVALID_ALIAS");
+        app.store.set_tier("alice", SubscriptionTier::Pro);
+        assert_eq!(shorten(&app, long)["short_code"], "summer-sale-2030-landing");
+    }
+
+    #[test]
+    fn test_hash_codes_reuse_links() {
+        let app = App {
+            store: MemoryStore::new(),
+            redirects: NoRedirects,
+            config: Config::from_vars(|name| (name == "CODE_STRATEGY").then(|| "hash".to_string())),
+        };
+        let shorten_as = |user: &str, url: &str| {
+            let req = ApiRequest::new().with_body(format!(r#"{{"url": "{}"}}"#, url)).with_user(Some(user.to_string()));
+            block_on(app.shorten(&req)).unwrap().1
+        };
+
+        let first = shorten_as("alice", "https://example.com");
+        let again = shorten_as("alice", "https://example.com");
+        assert_eq!((again.id, again.short_code), (first.id.clone(), first.short_code.clone()));
+        assert_ne!(shorten_as("bob", "https://example.com").short_code, first.short_code);
+        assert_ne!(shorten_as("alice", "https://example.org").short_code, first.short_code);
+
+        // An expired link isn't handed out again
+        let mut expired = first.clone();
+        expired.expires_at = Some("2000-01-01T00:00:00Z".to_string());
+        block_on(app.store.update_link(&expired)).unwrap();
+        assert_ne!(shorten_as("alice", "https://example.com").short_code, first.short_code);
+    }
+
+    #[test]
+    fn test_redirect_failures() {
+        let app = app();
+        let mut url = block_on(app.shorten(&ApiRequest::new().with_body(r#"{"url": "https://example.com"}"#)))
+            .unwrap()
+            .1;
+        url.expires_at = Some("2000-01-01T00:00:00Z".to_string());
+        block_on(app.store.publish_link(&url)).unwrap();
+
+        assert_eq!(respond(block_on(app.redirect(&url.short_code, &ApiRequest::new()))).status, 410);
+        assert_eq!(block_on(app.redirect("missing", &ApiRequest::new())).unwrap_err(), ApiError::LinkNotFound);
+        assert_eq!(block_on(app.redirect("api", &ApiRequest::new())).unwrap_err(), ApiError::NotFound);
+        assert!(app.store.clicks().is_empty());
+    }
+
+    #[test]
+    fn test_blocked_destination() {
+        let app = app();
+        let body = shorten(&app, serde_json::json!({ "url": "https://phish.example/login" }));
+        let code = body["short_code"].as_str().unwrap().to_string();
+
+        let block = r#"{"kind": "domain", "value": " Phish.Example "}"#;
+        let admin = || ApiRequest::new().with_body(block).with_header("X-Admin-Token", "admin-secret");
+        let intruder = ApiRequest::new().with_body(block).with_header("X-Admin-Token", "admin-secreT");
+        assert_eq!(respond(block_on(app.add_block_entry(&intruder))).status, 403);
+        assert_eq!(block_on(app.add_block_entry(&ApiRequest::new().with_body(block))).unwrap_err(), ApiError::Forbidden);
+
+        let response = block_on(app.add_block_entry(&admin())).unwrap();
+        assert_eq!(response.status, 201);
+        let entry: BlockEntry = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(entry.value, "phish.example");
+        // Blocking it again is a no-op that returns the same entry
+        let response = block_on(app.add_block_entry(&admin())).unwrap();
+        assert_eq!((response.status, serde_json::from_str::<BlockEntry>(&response.body).unwrap().id), (200, entry.id.clone()));
+
+        let body = shorten(&app, serde_json::json!({ "url": "https://phish.example/other" }));
+        assert_eq!(body["code"], "BLOCKED_DESTINATION");
+        assert_eq!(block_on(app.redirect(&code, &ApiRequest::new())).unwrap().status, 403);
+
+        let listed: Vec<BlockEntry> = serde_json::from_str(&block_on(app.list_blocklist(&admin())).unwrap().body).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(block_on(app.remove_block_entry(&entry.id, &admin())).unwrap().status, 204);
+        assert_eq!(block_on(app.remove_block_entry(&entry.id, &admin())).unwrap_err(), ApiError::NotFound);
+        assert_eq!(block_on(app.redirect(&code, &ApiRequest::new())).unwrap().status, 302);
+    }
+
+    #[test]
+    fn test_metadata_keeps_later_edits() {
+        struct Page;
+
+        impl PageFetcher for Page {
+            async fn fetch_html(&self, _url: &str) -> Result<String, String> {
+                Ok("<head><title>Example</title></head>".to_string())
+            }
+        }
+
+        let app = app();
+        let created = block_on(app.shorten(&ApiRequest::new().with_body(r#"{"url": "https://example.com"}"#))).unwrap().1;
+        let code = created.short_code.clone();
+        let mut edited = created.clone();
+        edited.original_url = "https://example.org/".to_string();
+        block_on(app.store.update_link(&edited)).unwrap();
+
+        // The scrape started with the link as created, but must not undo the edit
+        block_on(store_page_metadata(&app.store, &Page, created)).unwrap();
+        let link = block_on(app.store.get_link(&code)).unwrap().unwrap();
+        assert_eq!(link.title.as_deref(), Some("Example"));
+        assert_eq!(link.original_url, "https://example.org/");
+    }
+
+    #[test]
+    fn test_update_and_delete_link() {
+        let app = app();
+        let body = shorten(&app, serde_json::json!({ "url": "https://example.com" }));
+        let code = body["short_code"].as_str().unwrap().to_string();
+        let alice = |body: &str| ApiRequest::new().with_body(body).with_user(Some("alice".to_string()));
+
+        let bob = ApiRequest::new().with_body("{}").with_user(Some("bob".to_string()));
+        assert_eq!(respond(block_on(app.update_link(&code, &bob))).status, 404);
+        assert_eq!(block_on(app.delete_link(&code, &bob)).unwrap_err(), ApiError::LinkNotFound);
+
+        let response = block_on(app.update_link(&code, &alice(r#"{"url": "https://example.org", "expires_at": "2030-01-01T00:00:00Z"}"#))).unwrap();
+        let updated: Url = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(updated.original_url, "https://example.org");
+        assert_eq!(app.store.row(&code).unwrap().original_url, "https://example.org");
+        let response = block_on(app.redirect(&code, &ApiRequest::new())).unwrap();
+        assert_eq!(response.header("Location"), Some("https://example.org/"));
+
+        let response = respond(block_on(app.update_link(&code, &alice(r#"{"expires_at": "tomorrow"}"#))));
+        assert_eq!(response.status, 400);
+        assert!(response.body.contains("INVALID_EXPIRY"));
+
+        assert_eq!(block_on(app.delete_link(&code, &alice(""))).unwrap().status, 204);
+        assert!(app.store.row(&code).is_none());
+        assert!(app.store.clicks().is_empty());
+        assert_eq!(respond(block_on(app.redirect(&code, &ApiRequest::new()))).status, 404);
+    }
+
+    #[test]
+    fn test_list_links() {
+        let app = app();
+        shorten(&app, serde_json::json!({ "url": "https://example.com" }));
+
+        assert_eq!(respond(block_on(app.list_links(&ApiRequest::new()))).status, 401);
+        let response = block_on(app.list_links(&ApiRequest::new().with_user(Some("alice".to_string())))).unwrap();
+        let links: Vec<Url> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].original_url, "https://example.com");
+    }
+
+    #[test]
+    fn test_sessions() {
+        let app = app();
+        assert_eq!(block_on(app.authenticate(&ApiRequest::new())).unwrap(), None);
+
+        let response = block_on(app.start_session("alice")).unwrap();
+        let session: SessionResponse = serde_json::from_str(&response.body).unwrap();
+        let req = ApiRequest::new().with_header("Authorization", &format!("Bearer {}", session.token));
+        assert_eq!(block_on(app.authenticate(&req)).unwrap(), Some("alice".to_string()));
+
+        // Forged and malformed tokens are rejected, not treated as anonymous
+        let forged = ApiRequest::new().with_header("Authorization", &format!("Bearer {}x", session.token));
+        assert!(matches!(block_on(app.authenticate(&forged)), Err(ApiError::InvalidToken(_))));
+        let basic = ApiRequest::new().with_header("Authorization", "Basic YWxpY2U6");
+        assert!(matches!(block_on(app.authenticate(&basic)), Err(ApiError::InvalidToken(_))));
+
+        // Logging out revokes the token before it expires
+        assert_eq!(block_on(app.end_session(&req)).unwrap().status, 204);
+        assert!(matches!(block_on(app.authenticate(&req)), Err(ApiError::InvalidToken(_))));
+        assert_eq!(respond(block_on(app.end_session(&ApiRequest::new()))).status, 401);
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let app = app();
+        let alice = ApiRequest::new().with_user(Some("alice".to_string()));
+        let named = |req: &ApiRequest, name: &str| req.clone().with_body(serde_json::json!({ "name": name }).to_string());
+        // Free users don't get API access
+        assert_eq!(block_on(app.create_api_key(&named(&alice, "CI"))).unwrap_err(), ApiError::ApiAccessRequired);
+        app.store.set_tier("alice", SubscriptionTier::Pro);
+        assert_eq!(block_on(app.create_api_key(&named(&alice, "  "))).unwrap_err(), ApiError::InvalidKeyName);
+
+        let response = block_on(app.create_api_key(&named(&alice, "CI"))).unwrap();
+        let created: CreatedApiKey = serde_json::from_str(&response.body).unwrap();
+        assert!(created.key.starts_with("sk_") && created.key.starts_with(&created.api_key.prefix));
+        // Only the hash is stored
+        let stored = block_on(app.store.find_api_key(&auth::hash_api_key(&created.key))).unwrap().unwrap();
+        assert_ne!(stored.key_hash, created.key);
+
+        // The key authenticates as its owner and records the use
+        let with_key = ApiRequest::new().with_header("Authorization", &format!("Bearer {}", created.key));
+        assert_eq!(block_on(app.authenticate(&with_key)).unwrap(), Some("alice".to_string()));
+        let listed: Vec<ApiKeyInfo> = serde_json::from_str(&block_on(app.list_api_keys(&alice)).unwrap().body).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].last_used_at.is_some());
+        // Uses within a minute don't rewrite it
+        block_on(app.authenticate(&with_key)).unwrap();
+        let relisted: Vec<ApiKeyInfo> = serde_json::from_str(&block_on(app.list_api_keys(&alice)).unwrap().body).unwrap();
+        assert_eq!(relisted[0].last_used_at, listed[0].last_used_at);
+
+        let id = created.api_key.id;
+        let bob = ApiRequest::new().with_user(Some("bob".to_string()));
+        assert_eq!(block_on(app.revoke_api_key(&id, &bob)).unwrap_err(), ApiError::ApiKeyNotFound);
+        let renamed = block_on(app.rename_api_key(&id, &named(&alice, "Deploy"))).unwrap();
+        assert_eq!(serde_json::from_str::<ApiKeyInfo>(&renamed.body).unwrap().name, "Deploy");
+
+        // Keys stop working on a tier without API access
+        app.store.set_tier("alice", SubscriptionTier::Free);
+        assert_eq!(block_on(app.authenticate(&with_key)).unwrap_err(), ApiError::ApiAccessRequired);
+        app.store.set_tier("alice", SubscriptionTier::Pro);
+
+        assert_eq!(block_on(app.revoke_api_key(&id, &alice)).unwrap().status, 204);
+        assert!(matches!(block_on(app.authenticate(&with_key)), Err(ApiError::InvalidToken(_))));
+        assert_eq!(block_on(app.list_api_keys(&alice)).unwrap().body, "[]");
+    }
+}
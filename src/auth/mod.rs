@@ -0,0 +1,426 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Google's signing keys for ID tokens
+pub const GOOGLE_CERTS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+const GOOGLE_ISSUERS: [&str; 2] = ["accounts.google.com", "https://accounts.google.com"];
+// How long keys are cached when the response carries no max-age, in seconds
+const DEFAULT_KEYS_TTL: i64 = 3600;
+// Clock skew tolerated when checking `exp`, in seconds
+const LEEWAY: i64 = 60;
+// Minimum time between refetches triggered by unknown key ids, in seconds
+const MIN_REFETCH_INTERVAL: i64 = 60;
+/// Every API key starts with this, which tells them apart from session tokens
+pub const API_KEY_PREFIX: &str = "sk_";
+// Characters of a key kept in the clear so users can tell their keys apart
+const API_KEY_DISPLAY_LEN: usize = 11;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    Malformed,
+    UnsupportedAlgorithm(String),
+    UnknownKey,
+    BadSignature,
+    WrongIssuer,
+    WrongAudience,
+    Expired,
+    /// The key set couldn't be fetched
+    Keys(String),
+    /// `JWT_SECRET` is not configured
+    MissingSecret,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Malformed => write!(f, "Malformed token"),
+            AuthError::UnsupportedAlgorithm(alg) => write!(f, "Unsupported algorithm {}", alg),
+            AuthError::UnknownKey => write!(f, "Token signed with an unknown key"),
+            AuthError::BadSignature => write!(f, "Invalid signature"),
+            AuthError::WrongIssuer => write!(f, "Token not issued by Google"),
+            AuthError::WrongAudience => write!(f, "Token issued for another client"),
+            AuthError::Expired => write!(f, "Token expired"),
+            AuthError::Keys(e) => write!(f, "Could not fetch signing keys: {}", e),
+            AuthError::MissingSecret => write!(f, "JWT_SECRET is not set"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// One RSA key of a JSON Web Key Set
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jwk {
+    pub kid: String,
+    pub kty: String,
+    /// Base64url big-endian modulus and exponent
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// A fetched key set and how long it may be cached, in seconds
+pub struct FetchedKeys {
+    pub jwks: Jwks,
+    pub max_age: Option<i64>,
+}
+
+/// Where signing keys come from, so tests can use a local key set
+#[allow(async_fn_in_trait)]
+pub trait KeySource {
+    async fn fetch_keys(&self) -> Result<FetchedKeys, String>;
+}
+
+/// Fetches Google's keys through the Workers `fetch` API
+#[cfg(target_arch = "wasm32")]
+pub struct WorkerKeySource;
+
+#[cfg(target_arch = "wasm32")]
+impl KeySource for WorkerKeySource {
+    async fn fetch_keys(&self) -> Result<FetchedKeys, String> {
+        let url = url::Url::parse(GOOGLE_CERTS_URL).map_err(|e| e.to_string())?;
+        let mut resp = worker::Fetch::Url(url).send().await.map_err(|e| e.to_string())?;
+        if resp.status_code() != 200 {
+            return Err(format!("HTTP {}", resp.status_code()));
+        }
+
+        let cache_control = resp.headers().get("Cache-Control").ok().flatten();
+        let jwks = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(FetchedKeys {
+            jwks,
+            max_age: cache_control.as_deref().and_then(max_age),
+        })
+    }
+}
+
+/// `max-age` of a Cache-Control header
+pub fn max_age(cache_control: &str) -> Option<i64> {
+    cache_control
+        .split(',')
+        .find_map(|directive| directive.trim().strip_prefix("max-age="))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Keys kept between requests until they expire
+pub struct KeyCache {
+    entry: Mutex<Option<CachedKeys>>,
+}
+
+struct CachedKeys {
+    jwks: Jwks,
+    // Unix times the set was fetched and expires
+    fetched_at: i64,
+    expires_at: i64,
+}
+
+impl KeyCache {
+    pub const fn new() -> Self {
+        Self { entry: Mutex::new(None) }
+    }
+
+    /// The cached keys, refetched once expired. With `refresh` (an unknown key id), they are
+    /// also refetched early, but at most once per `MIN_REFETCH_INTERVAL` so random key ids
+    /// can't make every request call Google.
+    async fn keys<K: KeySource>(&self, source: &K, now: i64, refresh: bool) -> Result<Jwks, AuthError> {
+        if let Some(cached) = self.entry.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            let refetch_allowed = now - cached.fetched_at >= MIN_REFETCH_INTERVAL;
+            if now < cached.expires_at && !(refresh && refetch_allowed) {
+                return Ok(cached.jwks.clone());
+            }
+        }
+
+        let fetched = source.fetch_keys().await.map_err(AuthError::Keys)?;
+        *self.entry.lock().unwrap_or_else(|e| e.into_inner()) = Some(CachedKeys {
+            jwks: fetched.jwks.clone(),
+            fetched_at: now,
+            expires_at: now + fetched.max_age.unwrap_or(DEFAULT_KEYS_TTL),
+        });
+        Ok(fetched.jwks)
+    }
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Claims of a verified Google ID token
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoogleClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub iss: String,
+    pub aud: String,
+    pub exp: i64,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// Verifies Google ID tokens locally, against keys from `source` cached in `cache`
+pub struct GoogleVerifier<'a, K> {
+    /// OAuth client IDs tokens may be issued for, from `GOOGLE_CLIENT_ID`
+    pub client_ids: &'a [String],
+    pub source: K,
+    pub cache: &'a KeyCache,
+}
+
+impl<K: KeySource> GoogleVerifier<'_, K> {
+    /// Check the RS256 signature, `iss`, `aud` and `exp` of `token` at unix time `now`
+    pub async fn verify(&self, token: &str, now: i64) -> Result<GoogleClaims, AuthError> {
+        let mut parts = token.split('.');
+        let (Some(header_b64), Some(claims_b64), Some(signature_b64), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(AuthError::Malformed);
+        };
+        let header: Header = decode_json(header_b64)?;
+        if header.alg != "RS256" {
+            return Err(AuthError::UnsupportedAlgorithm(header.alg));
+        }
+        let kid = header.kid.ok_or(AuthError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature_b64).map_err(|_| AuthError::Malformed)?;
+
+        // An unknown key id usually means Google rotated its keys since we cached them
+        let mut jwks = self.cache.keys(&self.source, now, false).await?;
+        if !jwks.keys.iter().any(|key| key.kid == kid) {
+            jwks = self.cache.keys(&self.source, now, true).await?;
+        }
+        let key = jwks.keys.iter().find(|key| key.kid == kid && key.kty == "RSA").ok_or(AuthError::UnknownKey)?;
+        let n = URL_SAFE_NO_PAD.decode(&key.n).map_err(|_| AuthError::UnknownKey)?;
+        let e = URL_SAFE_NO_PAD.decode(&key.e).map_err(|_| AuthError::UnknownKey)?;
+        let public_key =
+            RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e)).map_err(|_| AuthError::UnknownKey)?;
+
+        let signed = &token[..header_b64.len() + 1 + claims_b64.len()];
+        let hashed = Sha256::digest(signed.as_bytes());
+        public_key
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, &signature)
+            .map_err(|_| AuthError::BadSignature)?;
+
+        let claims: GoogleClaims = decode_json(claims_b64)?;
+        if !GOOGLE_ISSUERS.contains(&claims.iss.as_str()) {
+            return Err(AuthError::WrongIssuer);
+        }
+        if !self.client_ids.contains(&claims.aud) {
+            return Err(AuthError::WrongAudience);
+        }
+        if claims.exp + LEEWAY < now {
+            return Err(AuthError::Expired);
+        }
+        Ok(claims)
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(part: &str) -> Result<T, AuthError> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).map_err(|_| AuthError::Malformed)?;
+    serde_json::from_slice(&bytes).map_err(|_| AuthError::Malformed)
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn session_mac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length")
+}
+
+/// Claims of a first-party session token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionClaims {
+    /// User id
+    pub sub: String,
+    /// Session id, a row of the `sessions` table
+    pub sid: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Sign `claims` as an HS256 JWT with `secret` (`JWT_SECRET`)
+pub fn sign_session(secret: &str, claims: &SessionClaims) -> Result<String, AuthError> {
+    if secret.is_empty() {
+        return Err(AuthError::MissingSecret);
+    }
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).map_err(|_| AuthError::Malformed)?);
+    let signed = format!("{}.{}", header, claims);
+    let signature = URL_SAFE_NO_PAD.encode(session_mac(secret).chain_update(signed.as_bytes()).finalize().into_bytes());
+    Ok(format!("{}.{}", signed, signature))
+}
+
+/// Check the signature and expiry of a token from `sign_session` at unix time `now`.
+/// Revocation is checked against the store by the caller.
+pub fn verify_session(secret: &str, token: &str, now: i64) -> Result<SessionClaims, AuthError> {
+    if secret.is_empty() {
+        return Err(AuthError::MissingSecret);
+    }
+    let (signed, signature_b64) = token.rsplit_once('.').ok_or(AuthError::Malformed)?;
+    let (header_b64, claims_b64) = signed.split_once('.').ok_or(AuthError::Malformed)?;
+    let header: Header = decode_json(header_b64)?;
+    if header.alg != "HS256" {
+        return Err(AuthError::UnsupportedAlgorithm(header.alg));
+    }
+
+    let signature = URL_SAFE_NO_PAD.decode(signature_b64).map_err(|_| AuthError::Malformed)?;
+    // Constant-time, so timing doesn't reveal how much of the signature matched
+    session_mac(secret)
+        .chain_update(signed.as_bytes())
+        .verify_slice(&signature)
+        .map_err(|_| AuthError::BadSignature)?;
+
+    let claims: SessionClaims = decode_json(claims_b64)?;
+    if claims.exp < now {
+        return Err(AuthError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Hex SHA-256 of an API key, the only form in which keys are stored.
+/// Keys are long and random, so an unsalted hash is enough.
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// The part of an API key that may be shown after creation, e.g. `sk_1a2b3c4d`
+pub fn api_key_prefix(key: &str) -> String {
+    key.chars().take(API_KEY_DISPLAY_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::block_on;
+    use rsa::RsaPrivateKey;
+    use serde_json::json;
+    use std::cell::Cell;
+
+    // A 1024-bit key generated with `openssl genrsa`; small keys keep the tests fast
+    const TEST_N: &str = "b919dad1612527d54ab76699b29aba13bc09f0195d7029c8b080db74510660dac5e6ed9c8f8b439ac1b96cf44ed8c457aed4efb2eb2bc32c39f8e9d1e1206675b8062d008fa1d47fa016110252f1c71cad6dfedc7ea454b598aaad2fe04a5b87c5c816ec9a544a72e5f6ce9dba0393aa95f2a816e011b7ba40f598c4c8d86d29";
+    const TEST_D: &str = "03a856219308d7eba8094db6b5c7c6eb82e8b35cdcf2cd7090c44b11d65408efe9bf316cc98d57eda3ec5511d2c907c02fcd375b52b84fa7fd114515879c4b49f99316c6739c299c803da3b0d5e893ce1a1dcab7735f2194ef4b4c9220e6ac1fc41fa90eba22fd1382d00360d525ed0b070948abb25882bfe78e6eff72f7a47d";
+    const TEST_E: [u8; 3] = [0x01, 0x00, 0x01];
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // Serves the test key under `kid`, counting fetches
+    struct LocalKeys {
+        kid: &'static str,
+        fetches: Cell<usize>,
+    }
+
+    impl KeySource for LocalKeys {
+        async fn fetch_keys(&self) -> Result<FetchedKeys, String> {
+            self.fetches.set(self.fetches.get() + 1);
+            Ok(FetchedKeys {
+                jwks: Jwks {
+                    keys: vec![Jwk {
+                        kid: self.kid.to_string(),
+                        kty: "RSA".to_string(),
+                        n: URL_SAFE_NO_PAD.encode(hex(TEST_N)),
+                        e: URL_SAFE_NO_PAD.encode(TEST_E),
+                    }],
+                },
+                max_age: Some(600),
+            })
+        }
+    }
+
+    fn google_token(kid: &str, claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "RS256", "kid": kid, "typ": "JWT" }).to_string());
+        let signed = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let key = RsaPrivateKey::from_components(
+            BigUint::from_bytes_be(&hex(TEST_N)),
+            BigUint::from_bytes_be(&TEST_E),
+            BigUint::from_bytes_be(&hex(TEST_D)),
+            vec![],
+        )
+        .unwrap();
+        let signature = key.sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(signed.as_bytes())).unwrap();
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn google_claims(aud: &str, exp: i64) -> serde_json::Value {
+        json!({ "sub": "alice", "email": "alice@example.com", "iss": "https://accounts.google.com", "aud": aud, "exp": exp })
+    }
+
+    #[test]
+    fn test_verify_google_token() {
+        let client_ids = vec!["client-1".to_string()];
+        let cache = KeyCache::new();
+        let verifier = GoogleVerifier {
+            client_ids: &client_ids,
+            source: LocalKeys { kid: "k1", fetches: Cell::new(0) },
+            cache: &cache,
+        };
+        let now = 1_700_000_000;
+
+        let valid = google_token("k1", google_claims("client-1", now + 300));
+        assert_eq!(block_on(verifier.verify(&valid, now)).unwrap().sub, "alice");
+        // Cached: the second verification doesn't refetch
+        assert_eq!(block_on(verifier.verify(&valid, now + 10)).unwrap().email.as_deref(), Some("alice@example.com"));
+        assert_eq!(verifier.source.fetches.get(), 1);
+
+        let check = |token: &str| block_on(verifier.verify(token, now)).unwrap_err();
+        assert_eq!(check(&google_token("k1", google_claims("client-2", now + 300))), AuthError::WrongAudience);
+        assert_eq!(check(&google_token("k1", google_claims("client-1", now - 120))), AuthError::Expired);
+        let mut issuer = google_claims("client-1", now + 300);
+        issuer["iss"] = json!("https://evil.example");
+        assert_eq!(check(&google_token("k1", issuer)), AuthError::WrongIssuer);
+        assert_eq!(check("not-a-token"), AuthError::Malformed);
+
+        // A forged payload under a genuine signature
+        let (signed, signature) = valid.rsplit_once('.').unwrap();
+        let (header, _) = signed.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(google_claims("client-1", now + 99_999).to_string());
+        assert_eq!(check(&format!("{}.{}.{}", header, forged, signature)), AuthError::BadSignature);
+
+        // Unknown key ids refetch the set, but not more than once per interval
+        let unknown = google_token("k2", google_claims("client-1", now + 300));
+        assert_eq!(check(&unknown), AuthError::UnknownKey);
+        assert_eq!(verifier.source.fetches.get(), 1);
+        let later = now + MIN_REFETCH_INTERVAL;
+        assert_eq!(block_on(verifier.verify(&unknown, later)).unwrap_err(), AuthError::UnknownKey);
+        assert_eq!(block_on(verifier.verify(&unknown, later + 1)).unwrap_err(), AuthError::UnknownKey);
+        assert_eq!(verifier.source.fetches.get(), 2);
+    }
+
+    #[test]
+    fn test_session_tokens() {
+        let claims = SessionClaims {
+            sub: "alice".to_string(),
+            sid: "s1".to_string(),
+            iat: 1_700_000_000,
+            exp: 1_700_003_600,
+        };
+        let token = sign_session("secret", &claims).unwrap();
+        assert_eq!(verify_session("secret", &token, claims.iat), Ok(claims.clone()));
+
+        assert_eq!(verify_session("other", &token, claims.iat), Err(AuthError::BadSignature));
+        assert_eq!(verify_session("secret", &token, claims.exp + 1), Err(AuthError::Expired));
+        assert_eq!(verify_session("", &token, claims.iat), Err(AuthError::MissingSecret));
+        assert_eq!(sign_session("", &claims), Err(AuthError::MissingSecret));
+
+        // A Google token is not a session token
+        let google = google_token("k1", google_claims("client-1", claims.exp));
+        assert_eq!(verify_session("secret", &google, claims.iat), Err(AuthError::UnsupportedAlgorithm("RS256".to_string())));
+    }
+
+    #[test]
+    fn test_max_age() {
+        assert_eq!(max_age("public, max-age=19845, must-revalidate, no-transform"), Some(19845));
+        assert_eq!(max_age("no-cache"), None);
+    }
+}
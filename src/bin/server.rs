@@ -0,0 +1,51 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use url_shortener::api::{App, Config};
+use url_shortener::server::{serve, HttpFetcher, HttpRedirects, HttpStatusChecker, ServeOptions};
+use url_shortener::store::{SqliteStore, StoreResult};
+
+/// Serve the shortener API from a local SQLite database
+#[derive(Parser)]
+#[command(name = "shortlink-server", version)]
+struct Args {
+    /// SQLite database file; created and migrated on start
+    #[arg(long, env = "SHORTLINK_DB", default_value = "shortlink.db")]
+    db: String,
+    /// Address to listen on
+    #[arg(long, env = "SHORTLINK_LISTEN", default_value = "127.0.0.1:8787")]
+    listen: String,
+    /// Requests handled at once, each with its own database connection
+    #[arg(long, env = "SHORTLINK_THREADS", default_value_t = 8)]
+    threads: usize,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Same variables as wrangler.toml, read from the environment
+    let listen = args.listen.clone();
+    let var = |name: &str| match std::env::var(name) {
+        Ok(value) => Some(value),
+        Err(_) if name == "BASE_URL" => Some(format!("http://{}", listen)),
+        Err(_) => None,
+    };
+    let options = ServeOptions::from_vars(&args.listen, args.threads, var);
+
+    // Every thread gets its own connections; redirect resolution reads our own links through a second one
+    let open = || -> StoreResult<App<SqliteStore, HttpRedirects<SqliteStore>>> {
+        Ok(App {
+            store: SqliteStore::open(&args.db)?,
+            redirects: HttpRedirects::new(SqliteStore::open(&args.db)?),
+            config: Config::from_vars(var),
+        })
+    };
+
+    match serve(open, &HttpFetcher::default(), &HttpStatusChecker::default(), &options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}: {}", args.db, e);
+            ExitCode::FAILURE
+        }
+    }
+}
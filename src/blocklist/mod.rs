@@ -0,0 +1,229 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockKind {
+    /// Blocks the domain and all of its subdomains
+    Domain,
+    /// Glob over the full URL where `*` matches any run of characters
+    Pattern,
+}
+
+impl BlockKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockKind::Domain => "domain",
+            BlockKind::Pattern => "pattern",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockEntry {
+    pub id: String,
+    pub kind: BlockKind,
+    pub value: String,
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewBlockEntry {
+    pub kind: BlockKind,
+    pub value: String,
+    pub reason: Option<String>,
+}
+
+impl NewBlockEntry {
+    /// Lowercase the value and check it can ever match
+    pub fn normalized(self) -> Option<NewBlockEntry> {
+        let value = self.value.trim().to_lowercase();
+        let valid = match self.kind {
+            BlockKind::Domain => !value.is_empty() && !value.contains(['/', ':', '*']),
+            BlockKind::Pattern => !value.is_empty() && value != "*",
+        };
+        valid.then_some(NewBlockEntry { value, ..self })
+    }
+}
+
+/// Seconds a cached blocklist is used before it is read again
+pub const BLOCKLIST_TTL: i64 = 60;
+
+/// The blocklist kept between requests, so redirects don't read it from the
+/// database on every click. Changes made through the owning store clear it at
+/// once; other instances pick them up within `BLOCKLIST_TTL`.
+pub struct BlocklistCache {
+    // Unix time the entries were read, and the entries
+    entry: Mutex<Option<(i64, Vec<BlockEntry>)>>,
+}
+
+impl BlocklistCache {
+    pub const fn new() -> Self {
+        Self { entry: Mutex::new(None) }
+    }
+
+    /// The cached entries, unless they are older than `BLOCKLIST_TTL` at `now`
+    pub fn get(&self, now: i64) -> Option<Vec<BlockEntry>> {
+        match self.entry.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            Some((read_at, entries)) if now - read_at < BLOCKLIST_TTL => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set(&self, now: i64, entries: Vec<BlockEntry>) {
+        *self.entry.lock().unwrap_or_else(|e| e.into_inner()) = Some((now, entries));
+    }
+
+    pub fn invalidate(&self) {
+        *self.entry.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl Default for BlocklistCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Return the first entry that blocks `url`
+pub fn find_match<'a>(entries: &'a [BlockEntry], url: &str) -> Option<&'a BlockEntry> {
+    let url_lower = url.to_lowercase();
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_end_matches('.').to_string()));
+
+    entries.iter().find(|entry| match entry.kind {
+        BlockKind::Domain => host.as_deref().is_some_and(|h| domain_matches(h, &entry.value)),
+        BlockKind::Pattern => glob_matches(&entry.value.to_lowercase(), &url_lower),
+    })
+}
+
+/// `host` is `domain` itself or one of its subdomains
+pub fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.to_lowercase();
+    let domain = domain.to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Match `text` against a glob where `*` matches any (possibly empty) run of characters
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == t[ti] {
+            pi += 1;
+            ti += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last `*` swallow one more character
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Interstitial shown instead of redirecting to a blocked destination
+pub fn warning_page(short_code: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="robots" content="noindex">
+    <title>Link disabled</title>
+    <style>
+        body {{ font-family: system-ui, sans-serif; background: #fef2f2; color: #111827; display: flex; align-items: center; justify-content: center; min-height: 100vh; margin: 0; }}
+        .card {{ background: #fff; border: 1px solid #fecaca; border-radius: 12px; padding: 32px; max-width: 480px; text-align: center; }}
+        h1 {{ color: #b91c1c; }}
+    </style>
+</head>
+<body>
+    <div class="card">
+        <h1>⚠️ Link disabled</h1>
+        <p>The short link <code>{}</code> points to a destination that has been flagged as malicious or phishing, so we are not redirecting you.</p>
+    </div>
+</body>
+</html>"#,
+        html_escape(short_code)
+    )
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: BlockKind, value: &str) -> BlockEntry {
+        BlockEntry {
+            id: value.to_string(),
+            kind,
+            value: value.to_string(),
+            reason: None,
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*/wp-login.php*", "https://evil.com/wp-login.php?x=1"));
+        assert!(glob_matches("https://*.example.com/*", "https://a.b.example.com/path"));
+        assert!(glob_matches("*paypal*verify*", "https://x.com/paypal-account/verify"));
+        assert!(!glob_matches("https://*.example.com/*", "https://example.org/path"));
+        assert!(!glob_matches("*login", "https://x.com/login/extra"));
+    }
+
+    #[test]
+    fn test_find_match() {
+        let entries = vec![
+            entry(BlockKind::Domain, "evil.com"),
+            entry(BlockKind::Pattern, "*free-gift-card*"),
+        ];
+
+        assert!(find_match(&entries, "https://evil.com/").is_some());
+        assert!(find_match(&entries, "https://login.EVIL.com/x").is_some());
+        assert!(find_match(&entries, "https://ok.com/Free-Gift-Card").is_some());
+        assert!(find_match(&entries, "https://notevil.com/").is_none());
+        assert!(find_match(&entries, "https://example.com/?u=evil.com").is_none());
+    }
+
+    #[test]
+    fn test_normalized_entry() {
+        let new = |kind, value: &str| NewBlockEntry { kind, value: value.to_string(), reason: None };
+
+        assert_eq!(new(BlockKind::Domain, " Evil.COM ").normalized().unwrap().value, "evil.com");
+        assert!(new(BlockKind::Domain, "https://evil.com").normalized().is_none());
+        assert!(new(BlockKind::Pattern, "*").normalized().is_none());
+    }
+
+    #[test]
+    fn test_cache_expires() {
+        let cache = BlocklistCache::new();
+        assert!(cache.get(1000).is_none());
+
+        cache.set(1000, vec![entry(BlockKind::Domain, "evil.com")]);
+        assert_eq!(cache.get(1000 + BLOCKLIST_TTL - 1).unwrap().len(), 1);
+        assert!(cache.get(1000 + BLOCKLIST_TTL).is_none());
+
+        cache.set(1000, Vec::new());
+        cache.invalidate();
+        assert!(cache.get(1000).is_none());
+    }
+}
@@ -0,0 +1,380 @@
+use worker::*;
+use crate::alias::decode_path_segment;
+use crate::api::{deprecation_headers, store_page_metadata, versioned_successor, ApiRequest, ApiResponse, App, Config, API_V1};
+use crate::auth::{GoogleVerifier, KeyCache, WorkerKeySource};
+use crate::cors::{self, CorsPolicy};
+use crate::error::{ApiError, ApiResult};
+use crate::health::{self, WorkerStatusChecker};
+use crate::reconcile::{self, ReconcileMode};
+use crate::resolve::WorkerRedirects;
+use crate::scraper::WorkerFetcher;
+use crate::store::CloudflareStore;
+use crate::utils::current_timestamp;
+
+#[event(fetch)]
+async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
+    let path = req.path();
+    let method = req.method().to_string();
+    let origin = req.headers().get("Origin")?;
+    let cors = CorsPolicy::from_vars(|name| env.var(name).ok().map(|v| v.to_string()));
+
+    let mut response = if method == "OPTIONS" && cors::applies_to(&path) {
+        // Preflight for every API route
+        Response::empty()?.with_status(204)
+    } else {
+        // The worker context is shared with handlers so they can schedule background work
+        let router = Router::with_data(ctx);
+        let result = api_routes(api_routes(router, API_V1), "/api")
+            .get_async("/robots.txt", |_req, ctx| async move { into_response(app(&ctx)?.robots()) })
+            .get_async("/favicon.ico", |_req, ctx| async move { into_response(app(&ctx)?.favicon()) })
+            .get_async("/:code", handle_redirect)
+            .get_async("/", |_req, ctx| async move { into_response(app(&ctx)?.home()) })
+            .run(req, env)
+            .await;
+
+        let mut response = match result {
+            Ok(response) => response,
+            // Still answered with the JSON envelope and CORS headers, so browsers can read it
+            Err(e) => respond(Err(ApiError::Internal(e.to_string())))?,
+        };
+        if let Some(successor) = versioned_successor(&path) {
+            for (name, value) in deprecation_headers(&successor) {
+                response.headers_mut().set(name, &value)?;
+            }
+        }
+        response
+    };
+
+    for (name, value) in cors.headers(&method, &path, origin.as_deref()) {
+        response.headers_mut().set(name, &value)?;
+    }
+    Ok(response)
+}
+
+// Every API endpoint, mounted under `prefix`; the same set as `server::ROUTES`, which tests hold
+// against the OpenAPI document. CORS is added in `main`.
+fn api_routes<'a>(router: Router<'a, Context>, prefix: &str) -> Router<'a, Context> {
+    router
+        .post_async(&format!("{}/auth/session", prefix), handle_create_session)
+        .delete_async(&format!("{}/auth/session", prefix), handle_end_session)
+        .post_async(&format!("{}/keys", prefix), handle_create_api_key)
+        .get_async(&format!("{}/keys", prefix), handle_list_api_keys)
+        .patch_async(&format!("{}/keys/:id", prefix), handle_rename_api_key)
+        .delete_async(&format!("{}/keys/:id", prefix), handle_revoke_api_key)
+        .post_async(&format!("{}/shorten", prefix), handle_shorten)
+        .get_async(&format!("{}/urls", prefix), handle_list_urls)
+        .patch_async(&format!("{}/urls/:code", prefix), handle_update_url)
+        .delete_async(&format!("{}/urls/:code", prefix), handle_delete_url)
+        .get_async(&format!("{}/alias/:alias/available", prefix), handle_alias_available)
+        .get_async(&format!("{}/analytics/:code", prefix), handle_analytics)
+        // API description, generated from the models
+        .get_async(&format!("{}/openapi.json", prefix), |_req, ctx| async move {
+            into_response(app(&ctx)?.openapi())
+        })
+        .get_async(&format!("{}/docs", prefix), |_req, ctx| async move {
+            into_response(app(&ctx)?.docs())
+        })
+        // Admin: destination blocklist
+        .get_async(&format!("{}/admin/blocklist", prefix), handle_list_blocklist)
+        .post_async(&format!("{}/admin/blocklist", prefix), handle_add_blocklist)
+        .delete_async(&format!("{}/admin/blocklist/:id", prefix), handle_remove_blocklist)
+        .get_async(&format!("{}/admin/reconcile", prefix), handle_reconcile_reports)
+        .get_async(&format!("{}/admin/schema", prefix), handle_schema_report)
+}
+
+// Must match the reconciliation schedule in wrangler.toml; every other cron runs health checks
+const RECONCILE_CRON: &str = "17 3 * * *";
+
+#[event(scheduled)]
+async fn scheduled(event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    if event.cron() == RECONCILE_CRON {
+        if let Err(e) = run_reconciliation(&env).await {
+            console_log!("Reconciliation failed: {}", e);
+        }
+    } else if let Err(e) = run_health_checks(&env).await {
+        console_log!("Health check failed: {}", e);
+    }
+}
+
+// Find short codes that exist in only one of KV and D1, and repair them if `RECONCILE_MODE` is "repair"
+async fn run_reconciliation(env: &Env) -> Result<()> {
+    let store = CloudflareStore::from_env(env)?;
+    let mode = env
+        .var("RECONCILE_MODE")
+        .map(|v| ReconcileMode::parse(&v.to_string()))
+        .unwrap_or(ReconcileMode::Report);
+
+    let report = reconcile::run(&store, mode, chrono::Utc::now()).await?;
+    console_log!(
+        "Reconciliation: {} missing in D1, {} missing in KV, {} repaired, {} deleted, {} skipped, {} failed",
+        report.drift.missing_in_d1.len(),
+        report.drift.missing_in_kv.len(),
+        report.repaired.len(),
+        report.deleted.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+    Ok(())
+}
+
+// Check the batch of links that were checked least recently
+async fn run_health_checks(env: &Env) -> Result<()> {
+    let batch_size = env_number(env, "HEALTH_CHECK_BATCH_SIZE", health::DEFAULT_BATCH_SIZE);
+    let threshold = env_number(env, "HEALTH_CHECK_FAILURE_THRESHOLD", health::DEFAULT_FAILURE_THRESHOLD);
+
+    let store = CloudflareStore::from_env(env)?;
+    let checked = health::run(&store, &WorkerStatusChecker, batch_size, threshold, &current_timestamp()).await?;
+    console_log!("Health check: {} links checked", checked);
+    Ok(())
+}
+
+// Read a numeric `[vars]` entry, falling back to `default` when unset or malformed
+fn env_number<T: std::str::FromStr>(env: &Env, name: &str, default: T) -> T {
+    env.var(name)
+        .ok()
+        .and_then(|v| v.to_string().parse().ok())
+        .unwrap_or(default)
+}
+
+// Build the platform-independent app on top of this worker's bindings and vars
+fn app(ctx: &RouteContext<Context>) -> Result<App<CloudflareStore, WorkerRedirects>> {
+    Ok(App {
+        store: CloudflareStore::from_env(&ctx.env)?,
+        redirects: WorkerRedirects { kv: ctx.kv("URLS")? },
+        config: Config::from_vars(|name| ctx.var(name).ok().map(|v| v.to_string())),
+    })
+}
+
+// Send a handler's result, logging server-side failures
+fn respond(result: ApiResult<ApiResponse>) -> Result<Response> {
+    let api = match result {
+        Ok(response) => response,
+        Err(e) => {
+            if e.is_server_error() {
+                console_log!("{}", e);
+            }
+            e.into()
+        }
+    };
+    into_response(api)
+}
+
+fn into_response(api: ApiResponse) -> Result<Response> {
+    let mut headers = Headers::new();
+    for (name, value) in &api.headers {
+        headers.set(name, value)?;
+    }
+    Ok(Response::from_bytes(api.body.into_bytes())?
+        .with_status(api.status)
+        .with_headers(headers))
+}
+
+// Google's keys, kept for the lifetime of the isolate
+static GOOGLE_KEYS: KeyCache = KeyCache::new();
+
+// Resolve the caller from the session token in `Authorization`, if any
+async fn request_user_id(req: &Request, ctx: &RouteContext<Context>) -> ApiResult<Option<String>> {
+    let app = app(ctx).map_err(|e| ApiError::Internal(e.to_string()))?;
+    app.authenticate(&authorization(req)).await
+}
+
+// An API request carrying only the caller's `Authorization` header
+fn authorization(req: &Request) -> ApiRequest {
+    match req.headers().get("Authorization").ok().flatten() {
+        Some(value) => ApiRequest::new().with_header("Authorization", &value),
+        None => ApiRequest::new(),
+    }
+}
+
+async fn handle_create_session(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let body = req.text().await.unwrap_or_default();
+
+    let app = app(&ctx)?;
+    let google = GoogleVerifier {
+        client_ids: &app.config.google_client_ids,
+        source: WorkerKeySource,
+        cache: &GOOGLE_KEYS,
+    };
+    respond(app.create_session(&google, &ApiRequest::new().with_body(body)).await)
+}
+
+async fn handle_end_session(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    respond(app(&ctx)?.end_session(&authorization(&req)).await)
+}
+
+async fn handle_shorten(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    let body = req.text().await.unwrap_or_default();
+
+    let app = app(&ctx)?;
+    let (response, url) = match app.shorten(&ApiRequest::new().with_body(body).with_user(user_id)).await {
+        Ok(created) => created,
+        Err(e) => return respond(Err(e)),
+    };
+
+    // Scrape title and favicon after the response is sent
+    let store = app.store;
+    ctx.data.wait_until(async move {
+        if let Err(e) = store_page_metadata(&store, &WorkerFetcher, url).await {
+            console_log!("Metadata scrape failed: {}", e);
+        }
+    });
+
+    into_response(response)
+}
+
+async fn handle_create_api_key(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    let body = req.text().await.unwrap_or_default();
+
+    respond(app(&ctx)?.create_api_key(&ApiRequest::new().with_body(body).with_user(user_id)).await)
+}
+
+async fn handle_list_api_keys(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    respond(app(&ctx)?.list_api_keys(&ApiRequest::new().with_user(user_id)).await)
+}
+
+async fn handle_rename_api_key(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let id = match ctx.param("id") {
+        Some(id) => id.to_string(),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    let body = req.text().await.unwrap_or_default();
+
+    respond(app(&ctx)?.rename_api_key(&id, &ApiRequest::new().with_body(body).with_user(user_id)).await)
+}
+
+async fn handle_revoke_api_key(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let id = match ctx.param("id") {
+        Some(id) => id.to_string(),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+
+    respond(app(&ctx)?.revoke_api_key(&id, &ApiRequest::new().with_user(user_id)).await)
+}
+
+async fn handle_analytics(_req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let short_code = match ctx.param("code") {
+        Some(code) => decode_path_segment(code),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+
+    respond(app(&ctx)?.analytics(&short_code).await)
+}
+
+async fn handle_list_urls(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    respond(app(&ctx)?.list_links(&ApiRequest::new().with_user(user_id)).await)
+}
+
+async fn handle_update_url(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let short_code = match ctx.param("code") {
+        Some(code) => decode_path_segment(code),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+    let body = req.text().await.unwrap_or_default();
+
+    respond(app(&ctx)?.update_link(&short_code, &ApiRequest::new().with_body(body).with_user(user_id)).await)
+}
+
+async fn handle_delete_url(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let short_code = match ctx.param("code") {
+        Some(code) => decode_path_segment(code),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+
+    respond(app(&ctx)?.delete_link(&short_code, &ApiRequest::new().with_user(user_id)).await)
+}
+
+async fn handle_redirect(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let short_code = match ctx.param("code") {
+        Some(code) => decode_path_segment(code),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+
+    // Click analytics are read from the Cloudflare request headers
+    let mut api_req = ApiRequest::new();
+    for (name, value) in req.headers().entries() {
+        api_req = api_req.with_header(&name, &value);
+    }
+
+    respond(app(&ctx)?.redirect(&short_code, &api_req).await)
+}
+
+async fn handle_alias_available(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let alias = match ctx.param("alias") {
+        Some(alias) => decode_path_segment(alias),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+
+    let user_id = match request_user_id(&req, &ctx).await {
+        Ok(user_id) => user_id,
+        Err(e) => return respond(Err(e)),
+    };
+
+    respond(app(&ctx)?.alias_available(&alias, &ApiRequest::new().with_user(user_id)).await)
+}
+
+// An API request carrying only the caller's `X-Admin-Token` header, and the body if given
+fn admin_request(req: &Request, body: String) -> ApiRequest {
+    match req.headers().get("X-Admin-Token").ok().flatten() {
+        Some(value) => ApiRequest::new().with_header("X-Admin-Token", &value).with_body(body),
+        None => ApiRequest::new().with_body(body),
+    }
+}
+
+async fn handle_list_blocklist(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    respond(app(&ctx)?.list_blocklist(&admin_request(&req, String::new())).await)
+}
+
+async fn handle_add_blocklist(mut req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let body = req.text().await.unwrap_or_default();
+
+    respond(app(&ctx)?.add_block_entry(&admin_request(&req, body)).await)
+}
+
+async fn handle_remove_blocklist(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    let id = match ctx.param("id") {
+        Some(id) => id.to_string(),
+        None => return respond(Err(ApiError::NotFound)),
+    };
+
+    respond(app(&ctx)?.remove_block_entry(&id, &admin_request(&req, String::new())).await)
+}
+
+async fn handle_reconcile_reports(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    respond(app(&ctx)?.reconcile_reports(&admin_request(&req, String::new())).await)
+}
+
+async fn handle_schema_report(req: Request, ctx: RouteContext<Context>) -> Result<Response> {
+    respond(app(&ctx)?.schema_report(&admin_request(&req, String::new())).await)
+}
@@ -0,0 +1,252 @@
+mod words;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::utils::{generate_code, BASE62};
+
+pub use words::WordCodes;
+
+/// Length of generated codes when `CODE_LENGTH` is unset
+pub const DEFAULT_CODE_LENGTH: usize = 4;
+
+/// Base62 without characters that are easily misread: `0/O/o`, `1/l/I`
+pub const HUMAN_SAFE: &[u8] = b"23456789abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ";
+/// Lowercase letters and digits, for codes that get spoken aloud
+pub const LOWERCASE: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Characters generated codes are drawn from, set with the `CODE_ALPHABET` var
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alphabet(Vec<u8>);
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet(BASE62.to_vec())
+    }
+}
+
+impl Alphabet {
+    /// Accepts a preset name (`base62`, `human-safe`, `lowercase`) or a literal set of characters
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let chars = match value.to_lowercase().as_str() {
+            "base62" => BASE62.to_vec(),
+            "human-safe" | "human_safe" => HUMAN_SAFE.to_vec(),
+            "lowercase" => LOWERCASE.to_vec(),
+            _ => {
+                let mut chars = value.as_bytes().to_vec();
+                chars.sort_unstable();
+                chars.dedup();
+                chars
+            }
+        };
+
+        // Codes must also be valid aliases, so only ASCII alphanumerics, `-` and `_`
+        let valid = chars.len() >= 2
+            && chars.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'-' || *c == b'_');
+        valid.then_some(Alphabet(chars))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Codes reserved from the D1 sequence per request, i.e. the retry budget in sequence mode
+pub const SEQUENCE_BLOCK: u32 = 8;
+
+/// Produces candidate short codes; the caller checks each one for collisions
+pub trait CodeGenerator {
+    /// Candidate for the given 0-based attempt, or `None` once the generator gives up
+    fn candidate(&self, attempt: u32) -> Option<String>;
+}
+
+/// How generated codes are chosen, from the `CODE_STRATEGY` var
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodeStrategy {
+    /// Random codes with bounded retries that grow longer on collisions
+    Random,
+    /// Counter stored in D1 encoded over the alphabet; never collides, but is enumerable
+    Sequence,
+    /// SHA-256 of the destination, so the same input always yields the same first candidate
+    Hash,
+}
+
+/// Per-request choice of code format in `ShortenRequest`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeStyle {
+    /// Whatever `CODE_STRATEGY` produces
+    #[serde(alias = "random")]
+    Default,
+    /// Readable words, e.g. `brave-otter-42`
+    Words,
+}
+
+impl CodeStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "random" => Some(CodeStrategy::Random),
+            "sequence" | "counter" => Some(CodeStrategy::Sequence),
+            "hash" => Some(CodeStrategy::Hash),
+            _ => None,
+        }
+    }
+}
+
+pub struct RandomCodes {
+    pub alphabet: Alphabet,
+    pub length: usize,
+    pub max_attempts: u32,
+    /// Add one character after this many collisions
+    pub grow_every: u32,
+}
+
+impl RandomCodes {
+    pub fn new(alphabet: Alphabet, length: usize) -> Self {
+        Self {
+            alphabet,
+            length,
+            max_attempts: 12,
+            grow_every: 3,
+        }
+    }
+}
+
+impl CodeGenerator for RandomCodes {
+    fn candidate(&self, attempt: u32) -> Option<String> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let length = self.length + (attempt / self.grow_every.max(1)) as usize;
+        Some(generate_code(self.alphabet.as_bytes(), length))
+    }
+}
+
+pub struct HashCodes {
+    pub input: String,
+    pub alphabet: Alphabet,
+    pub length: usize,
+    pub max_attempts: u32,
+}
+
+impl HashCodes {
+    pub fn new(input: String, alphabet: Alphabet, length: usize) -> Self {
+        Self {
+            input,
+            alphabet,
+            length,
+            max_attempts: 8,
+        }
+    }
+}
+
+impl CodeGenerator for HashCodes {
+    fn candidate(&self, attempt: u32) -> Option<String> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        // Salt retries with the attempt number and grow by one character every other retry
+        let digest = Sha256::new()
+            .chain_update(self.input.as_bytes())
+            .chain_update(attempt.to_be_bytes())
+            .finalize();
+        let length = self.length + (attempt / 2) as usize;
+        let alphabet = self.alphabet.as_bytes();
+        Some(
+            digest
+                .iter()
+                .take(length)
+                .map(|b| alphabet[*b as usize % alphabet.len()] as char)
+                .collect(),
+        )
+    }
+}
+
+/// Codes for a block of counter values `first..first + count` reserved from D1
+pub struct SequenceCodes {
+    pub alphabet: Alphabet,
+    pub first: u64,
+    pub count: u32,
+    pub min_length: usize,
+}
+
+impl CodeGenerator for SequenceCodes {
+    fn candidate(&self, attempt: u32) -> Option<String> {
+        if attempt >= self.count {
+            return None;
+        }
+        Some(encode_number(self.first + attempt as u64, &self.alphabet, self.min_length))
+    }
+}
+
+/// Encode `n` using `alphabet` as digits, left-padded with its first digit to `min_length`
+pub fn encode_number(mut n: u64, alphabet: &Alphabet, min_length: usize) -> String {
+    let digits_of = alphabet.as_bytes();
+    let base = digits_of.len() as u64;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(digits_of[(n % base) as usize]);
+        n /= base;
+        if n == 0 {
+            break;
+        }
+    }
+    while digits.len() < min_length {
+        digits.push(digits_of[0]);
+    }
+    digits.iter().rev().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_random_codes_are_bounded_and_grow() {
+        let generator = RandomCodes::new(Alphabet::default(), 4);
+        assert_eq!(generator.candidate(0).unwrap().len(), 4);
+        assert_eq!(generator.candidate(3).unwrap().len(), 5);
+        assert_eq!(generator.candidate(11).unwrap().len(), 7);
+        assert_eq!(generator.candidate(12), None);
+    }
+
+    #[test]
+    fn test_hash_codes_are_deterministic() {
+        let a = HashCodes::new("https://example.com".to_string(), Alphabet::default(), 6);
+        let b = HashCodes::new("https://example.com".to_string(), Alphabet::default(), 6);
+        assert_eq!(a.candidate(0), b.candidate(0));
+        assert_ne!(a.candidate(0), a.candidate(1));
+        assert_eq!(a.candidate(0).unwrap().len(), 6);
+        assert_eq!(a.candidate(8), None);
+    }
+
+    #[test]
+    fn test_sequence_codes() {
+        let base62 = Alphabet::default();
+        assert_eq!(encode_number(0, &base62, 1), "0");
+        assert_eq!(encode_number(61, &base62, 1), "Z");
+        assert_eq!(encode_number(62, &base62, 1), "10");
+        assert_eq!(encode_number(5, &base62, 4), "0005");
+
+        let generator = SequenceCodes { alphabet: base62, first: 1000, count: 3, min_length: 4 };
+        let codes: HashSet<_> = (0..3).filter_map(|i| generator.candidate(i)).collect();
+        assert_eq!(codes.len(), 3);
+        assert_eq!(generator.candidate(3), None);
+    }
+
+    #[test]
+    fn test_alphabet_presets() {
+        let safe = Alphabet::parse("human-safe").unwrap();
+        assert!(!safe.as_bytes().iter().any(|c| b"0Oo1lI".contains(c)));
+
+        let code = RandomCodes::new(Alphabet::parse("lowercase").unwrap(), 8).candidate(0).unwrap();
+        assert!(code.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+
+        assert_eq!(Alphabet::parse("ab-ba").unwrap().as_bytes(), b"-ab");
+        assert_eq!(Alphabet::parse("a"), None);
+        assert_eq!(Alphabet::parse("ab/c"), None);
+    }
+}
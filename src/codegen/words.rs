@@ -0,0 +1,71 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::CodeGenerator;
+
+// Short, common and easy to spell words, so codes survive being read aloud
+const ADJECTIVES: &[&str] = &[
+    "amber", "bold", "brave", "bright", "calm", "clever", "cosy", "crisp", "curly", "daring",
+    "eager", "fancy", "fluffy", "fuzzy", "gentle", "giant", "glad", "golden", "grand", "happy",
+    "hazy", "humble", "jolly", "keen", "kind", "lively", "lucky", "mellow", "merry", "mighty",
+    "misty", "nimble", "noble", "plucky", "polite", "proud", "quick", "quiet", "rapid", "rosy",
+    "rusty", "shiny", "silent", "silver", "sleepy", "smart", "snowy", "sunny", "swift", "tidy",
+    "tiny", "vivid", "warm", "wild", "wise", "witty", "young", "zesty",
+];
+
+const NOUNS: &[&str] = &[
+    "badger", "beaver", "bison", "cactus", "canyon", "cedar", "comet", "coral", "crane", "dolphin",
+    "eagle", "falcon", "fern", "fox", "gecko", "glacier", "harbor", "hawk", "heron", "island",
+    "koala", "lagoon", "lemur", "lynx", "maple", "meadow", "meteor", "moose", "nebula", "ocean",
+    "orchid", "otter", "owl", "panda", "parrot", "pebble", "penguin", "pine", "planet", "puffin",
+    "rabbit", "raven", "river", "robin", "salmon", "seal", "sparrow", "spruce", "squid", "summit",
+    "tiger", "tulip", "turtle", "valley", "walrus", "willow", "wombat", "zebra",
+];
+
+/// Readable codes like `brave-otter-42`
+pub struct WordCodes {
+    pub max_attempts: u32,
+}
+
+impl Default for WordCodes {
+    fn default() -> Self {
+        Self { max_attempts: 12 }
+    }
+}
+
+impl CodeGenerator for WordCodes {
+    fn candidate(&self, attempt: u32) -> Option<String> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let adjective = ADJECTIVES.choose(&mut rng)?;
+        let noun = NOUNS.choose(&mut rng)?;
+        // Widen the number after repeated collisions, like random codes grow longer
+        let number = if attempt < 4 {
+            rng.gen_range(10..100)
+        } else {
+            rng.gen_range(100..1000)
+        };
+        Some(format!("{}-{}-{}", adjective, noun, number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_codes() {
+        let generator = WordCodes::default();
+        let code = generator.candidate(0).unwrap();
+        let parts: Vec<&str> = code.split('-').collect();
+
+        assert_eq!(parts.len(), 3);
+        assert!(ADJECTIVES.contains(&parts[0]));
+        assert!(NOUNS.contains(&parts[1]));
+        assert_eq!(parts[2].len(), 2);
+        assert_eq!(generator.candidate(4).unwrap().rsplit('-').next().unwrap().len(), 3);
+        assert_eq!(generator.candidate(12), None);
+    }
+}
@@ -0,0 +1,111 @@
+// Headers browsers may send on cross-origin API requests
+const ALLOWED_HEADERS: &str = "Content-Type, Authorization, X-Admin-Token";
+const ALLOWED_METHODS: &str = "GET, POST, PATCH, DELETE, OPTIONS";
+// Response headers scripts may read besides the safelisted ones
+const EXPOSED_HEADERS: &str = "Deprecation, Link";
+// How long browsers may cache a preflight result, in seconds
+const MAX_AGE: &str = "86400";
+
+/// Which origins may call the API from a browser, configured by `CORS_ORIGINS`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum CorsPolicy {
+    /// Any origin, without credentials
+    #[default]
+    Any,
+    /// Only these origins, with credentials
+    Origins(Vec<String>),
+}
+
+impl CorsPolicy {
+    /// Parse a comma-separated list of origins; `*` or an empty value allows any origin
+    pub fn parse(value: &str) -> Self {
+        let origins: Vec<String> = value
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_lowercase())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+
+        if origins.is_empty() || origins.iter().any(|origin| origin == "*") {
+            CorsPolicy::Any
+        } else {
+            CorsPolicy::Origins(origins)
+        }
+    }
+
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        var("CORS_ORIGINS").map(|v| Self::parse(&v)).unwrap_or_default()
+    }
+
+    /// CORS headers for the response to `method path` sent from `origin`.
+    /// Empty outside the API; preflight `OPTIONS` requests also get the allowed methods and headers.
+    pub fn headers(&self, method: &str, path: &str, origin: Option<&str>) -> Vec<(&'static str, String)> {
+        if !applies_to(path) {
+            return Vec::new();
+        }
+
+        let mut headers = match self {
+            // Browsers refuse credentials with a wildcard origin, so none are offered
+            CorsPolicy::Any => vec![("Access-Control-Allow-Origin", "*".to_string())],
+            CorsPolicy::Origins(allowed) => {
+                // The answer depends on the origin, so caches must not share it
+                let mut headers = vec![("Vary", "Origin".to_string())];
+                if let Some(origin) = origin.filter(|o| allowed.iter().any(|a| a.eq_ignore_ascii_case(o))) {
+                    headers.push(("Access-Control-Allow-Origin", origin.to_string()));
+                    headers.push(("Access-Control-Allow-Credentials", "true".to_string()));
+                }
+                headers
+            }
+        };
+
+        if method.eq_ignore_ascii_case("OPTIONS") {
+            headers.push(("Access-Control-Allow-Methods", ALLOWED_METHODS.to_string()));
+            headers.push(("Access-Control-Allow-Headers", ALLOWED_HEADERS.to_string()));
+            headers.push(("Access-Control-Max-Age", MAX_AGE.to_string()));
+        } else {
+            headers.push(("Access-Control-Expose-Headers", EXPOSED_HEADERS.to_string()));
+        }
+        headers
+    }
+}
+
+/// CORS only covers the API; redirects and static files are same-origin or navigations
+pub fn applies_to(path: &str) -> bool {
+    path.starts_with("/api/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(headers: &'a [(&str, String)], name: &str) -> Option<&'a str> {
+        headers.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CorsPolicy::parse(""), CorsPolicy::Any);
+        assert_eq!(CorsPolicy::parse(" * "), CorsPolicy::Any);
+        assert_eq!(
+            CorsPolicy::parse("https://Example.com/, http://localhost:8080"),
+            CorsPolicy::Origins(vec!["https://example.com".to_string(), "http://localhost:8080".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_headers() {
+        let any = CorsPolicy::Any.headers("GET", "/api/v1/urls", Some("https://evil.example"));
+        assert_eq!(header(&any, "Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(header(&any, "Access-Control-Allow-Credentials"), None);
+        assert!(CorsPolicy::Any.headers("GET", "/promo", Some("https://evil.example")).is_empty());
+
+        let policy = CorsPolicy::parse("https://app.example");
+        let allowed = policy.headers("OPTIONS", "/api/v1/analytics/promo", Some("https://app.example"));
+        assert_eq!(header(&allowed, "Access-Control-Allow-Origin"), Some("https://app.example"));
+        assert_eq!(header(&allowed, "Access-Control-Allow-Credentials"), Some("true"));
+        assert!(header(&allowed, "Access-Control-Allow-Headers").unwrap().contains("Authorization"));
+
+        let denied = policy.headers("GET", "/api/v1/urls", Some("https://evil.example"));
+        assert_eq!(header(&denied, "Access-Control-Allow-Origin"), None);
+        assert_eq!(header(&denied, "Vary"), Some("Origin"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use crate::alias::AliasError;
+use crate::models::ErrorResponse;
+use crate::store::StoreError;
+
+/// Everything the API can fail with. Each variant has a stable `code` that
+/// clients can match on; the message is for humans and may change.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    InvalidBody,
+    /// The request body is over the server's limit
+    BodyTooLarge,
+    InvalidUrl,
+    /// The destination can't be shortened, e.g. it loops back to us
+    InvalidDestination(String),
+    InvalidAlias(AliasError),
+    AliasTaken { suggestions: Vec<String> },
+    InvalidExpiry,
+    BlockedDestination,
+    /// Every candidate code was taken
+    CodeUnavailable,
+    Unauthenticated,
+    /// A bearer token was sent but can't be accepted, e.g. expired or revoked
+    InvalidToken(String),
+    Forbidden,
+    NotFound,
+    LinkNotFound,
+    LinkExpired,
+    /// API key names must be 1 to 64 characters
+    InvalidKeyName,
+    ApiKeyNotFound,
+    /// The caller's tier doesn't include API keys
+    ApiAccessRequired,
+    /// A storage backend failed; the detail is logged, never sent to clients
+    Storage(String),
+    Internal(String),
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidBody => "INVALID_BODY",
+            ApiError::BodyTooLarge => "BODY_TOO_LARGE",
+            ApiError::InvalidUrl => "INVALID_URL",
+            ApiError::InvalidDestination(_) => "INVALID_DESTINATION",
+            ApiError::InvalidAlias(_) => "INVALID_ALIAS",
+            ApiError::AliasTaken { .. } => "ALIAS_TAKEN",
+            ApiError::InvalidExpiry => "INVALID_EXPIRY",
+            ApiError::BlockedDestination => "BLOCKED_DESTINATION",
+            ApiError::CodeUnavailable => "CODE_UNAVAILABLE",
+            ApiError::Unauthenticated => "UNAUTHENTICATED",
+            ApiError::InvalidToken(_) => "INVALID_TOKEN",
+            ApiError::Forbidden => "FORBIDDEN",
+            ApiError::NotFound => "NOT_FOUND",
+            ApiError::LinkNotFound => "LINK_NOT_FOUND",
+            ApiError::LinkExpired => "LINK_EXPIRED",
+            ApiError::InvalidKeyName => "INVALID_KEY_NAME",
+            ApiError::ApiKeyNotFound => "API_KEY_NOT_FOUND",
+            ApiError::ApiAccessRequired => "API_ACCESS_REQUIRED",
+            ApiError::Storage(_) => "STORAGE_ERROR",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::InvalidBody
+            | ApiError::InvalidUrl
+            | ApiError::InvalidDestination(_)
+            | ApiError::InvalidAlias(_)
+            | ApiError::InvalidExpiry
+            | ApiError::InvalidKeyName => 400,
+            ApiError::Unauthenticated | ApiError::InvalidToken(_) => 401,
+            ApiError::Forbidden | ApiError::BlockedDestination | ApiError::ApiAccessRequired => 403,
+            ApiError::NotFound | ApiError::LinkNotFound | ApiError::ApiKeyNotFound => 404,
+            ApiError::AliasTaken { .. } => 409,
+            ApiError::LinkExpired => 410,
+            ApiError::BodyTooLarge => 413,
+            ApiError::Storage(_) | ApiError::Internal(_) => 500,
+            ApiError::CodeUnavailable => 503,
+        }
+    }
+
+    /// Server-side failures that should be logged
+    pub fn is_server_error(&self) -> bool {
+        self.status() >= 500
+    }
+
+    /// The JSON body sent to clients
+    pub fn to_response(&self) -> ErrorResponse {
+        let suggestions = match self {
+            ApiError::AliasTaken { suggestions } => Some(suggestions.clone()),
+            _ => None,
+        };
+        ErrorResponse {
+            error: self.message(),
+            code: self.code().to_string(),
+            suggestions,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::InvalidBody => "Invalid request body".to_string(),
+            ApiError::BodyTooLarge => "Request body is too large".to_string(),
+            ApiError::InvalidUrl => "Invalid URL format. Must be http:// or https://".to_string(),
+            ApiError::InvalidDestination(reason) => reason.clone(),
+            ApiError::InvalidAlias(e) => e.to_string(),
+            ApiError::AliasTaken { .. } => "Custom alias already taken".to_string(),
+            ApiError::InvalidExpiry => "Invalid expiry. Use an RFC 3339 timestamp".to_string(),
+            ApiError::BlockedDestination => "This destination has been blocked as malicious or phishing".to_string(),
+            ApiError::CodeUnavailable => "Could not allocate a short code. Please try again.".to_string(),
+            ApiError::Unauthenticated => "Authentication required".to_string(),
+            ApiError::InvalidToken(reason) => format!("Invalid token: {}", reason),
+            ApiError::Forbidden => "Forbidden".to_string(),
+            ApiError::NotFound => "Not found".to_string(),
+            ApiError::LinkNotFound => "URL not found".to_string(),
+            ApiError::LinkExpired => "URL has expired".to_string(),
+            ApiError::InvalidKeyName => "API key name must be 1 to 64 characters".to_string(),
+            ApiError::ApiKeyNotFound => "API key not found".to_string(),
+            ApiError::ApiAccessRequired => "API keys require a Pro or Business plan".to_string(),
+            ApiError::Storage(_) => "Storage error. Please try again.".to_string(),
+            ApiError::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+// Includes the internal detail, so this is what gets logged
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::Storage(detail) | ApiError::Internal(detail) => write!(f, "{}: {}", self.code(), detail),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        ApiError::Storage(e.0)
+    }
+}
+
+impl From<AliasError> for ApiError {
+    fn from(e: AliasError) -> Self {
+        ApiError::InvalidAlias(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let body = serde_json::to_value(ApiError::AliasTaken { suggestions: vec!["promo-2".to_string()] }.to_response()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": "Custom alias already taken", "code": "ALIAS_TAKEN", "suggestions": ["promo-2"] })
+        );
+
+        // Storage details stay out of the response but not out of the logs
+        let error = ApiError::from(StoreError("disk I/O error".to_string()));
+        assert_eq!(error.status(), 500);
+        assert_eq!(serde_json::to_value(error.to_response()).unwrap()["error"], "Storage error. Please try again.");
+        assert_eq!(error.to_string(), "STORAGE_ERROR: disk I/O error");
+    }
+}
@@ -0,0 +1,155 @@
+use serde::Deserialize;
+
+use crate::store::{MaintenanceStore, StoreResult};
+
+/// Links checked per scheduled run when `HEALTH_CHECK_BATCH_SIZE` is unset
+pub const DEFAULT_BATCH_SIZE: u32 = 40;
+/// Consecutive failures before a link is flagged when `HEALTH_CHECK_FAILURE_THRESHOLD` is unset
+pub const DEFAULT_FAILURE_THRESHOLD: i32 = 3;
+
+/// The columns of `urls` the health checker reads
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthRow {
+    pub short_code: String,
+    pub original_url: String,
+    pub consecutive_failures: Option<i32>,
+    pub broken_since: Option<String>,
+}
+
+/// New health state to write back for one link
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthUpdate {
+    pub last_status: Option<u16>,
+    pub last_checked_at: String,
+    pub consecutive_failures: i32,
+    pub broken_since: Option<String>,
+}
+
+/// Performs the actual request against a destination
+#[allow(async_fn_in_trait)]
+pub trait StatusChecker {
+    /// Returns the final HTTP status, or `None` if the destination was unreachable
+    async fn check_status(&self, url: &str) -> Option<u16>;
+}
+
+/// Checks destinations through the Workers `fetch` API
+#[cfg(target_arch = "wasm32")]
+pub struct WorkerStatusChecker;
+
+#[cfg(target_arch = "wasm32")]
+impl StatusChecker for WorkerStatusChecker {
+    async fn check_status(&self, url: &str) -> Option<u16> {
+        // Try a cheap HEAD first; some servers only implement GET
+        match fetch_status(url, worker::Method::Head).await {
+            Some(405) | Some(501) => fetch_status(url, worker::Method::Get).await,
+            status => status,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_status(url: &str, method: worker::Method) -> Option<u16> {
+    let mut init = worker::RequestInit::new();
+    init.with_method(method);
+    let req = worker::Request::new_with_init(url, &init).ok()?;
+    worker::Fetch::Request(req).send().await.ok().map(|resp| resp.status_code())
+}
+
+/// Check the `batch_size` links checked least recently and save the results.
+/// Returns how many links were checked.
+pub async fn run<S: MaintenanceStore, C: StatusChecker>(store: &S, checker: &C, batch_size: u32, threshold: i32, now: &str) -> StoreResult<usize> {
+    let rows = store.health_batch(batch_size).await?;
+
+    let mut updates = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let status = checker.check_status(&row.original_url).await;
+        updates.push((row.short_code.clone(), next_state(row, status, threshold, now)));
+    }
+
+    if !updates.is_empty() {
+        store.save_health(&updates).await?;
+    }
+    Ok(rows.len())
+}
+
+/// Whether a check result counts towards flagging the link as broken.
+/// Auth walls and rate limits are common for bots, so only dead pages and server errors count.
+pub fn is_failure(status: Option<u16>) -> bool {
+    match status {
+        None => true,
+        Some(code) => code == 404 || code == 410 || code >= 500,
+    }
+}
+
+/// Fold one check result into a link's previous health state
+pub fn next_state(row: &HealthRow, status: Option<u16>, threshold: i32, now: &str) -> HealthUpdate {
+    if !is_failure(status) {
+        return HealthUpdate {
+            last_status: status,
+            last_checked_at: now.to_string(),
+            consecutive_failures: 0,
+            broken_since: None,
+        };
+    }
+
+    let failures = row.consecutive_failures.unwrap_or(0) + 1;
+    let broken_since = match &row.broken_since {
+        Some(since) => Some(since.clone()),
+        None if failures >= threshold => Some(now.to_string()),
+        None => None,
+    };
+
+    HealthUpdate {
+        last_status: status,
+        last_checked_at: now.to_string(),
+        consecutive_failures: failures,
+        broken_since,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(failures: i32, broken_since: Option<&str>) -> HealthRow {
+        HealthRow {
+            short_code: "abc".to_string(),
+            original_url: "https://example.com".to_string(),
+            consecutive_failures: Some(failures),
+            broken_since: broken_since.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_is_failure() {
+        assert!(is_failure(None));
+        assert!(is_failure(Some(404)));
+        assert!(is_failure(Some(503)));
+        assert!(!is_failure(Some(200)));
+        assert!(!is_failure(Some(403)));
+        assert!(!is_failure(Some(429)));
+    }
+
+    #[test]
+    fn test_next_state_flags_after_threshold() {
+        let update = next_state(&row(1, None), Some(404), 3, "t2");
+        assert_eq!(update.consecutive_failures, 2);
+        assert_eq!(update.broken_since, None);
+
+        let update = next_state(&row(2, None), None, 3, "t3");
+        assert_eq!(update.consecutive_failures, 3);
+        assert_eq!(update.broken_since.as_deref(), Some("t3"));
+
+        // Stays flagged from the first time it crossed the threshold
+        let update = next_state(&row(5, Some("t3")), Some(500), 3, "t6");
+        assert_eq!(update.broken_since.as_deref(), Some("t3"));
+    }
+
+    #[test]
+    fn test_next_state_recovers() {
+        let update = next_state(&row(4, Some("t1")), Some(200), 3, "t5");
+        assert_eq!(update.last_status, Some(200));
+        assert_eq!(update.consecutive_failures, 0);
+        assert_eq!(update.broken_since, None);
+    }
+}
@@ -1,3 +1,323 @@
-fn main() {
-    println!("Hello, world!");
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use url_shortener::models::{AnalyticsResponse, ApiKeyInfo, CreatedApiKey, ShortenResponse, Url};
+
+/// Manage short links through the shortener API
+#[derive(Parser)]
+#[command(name = "shortlink", version)]
+struct Cli {
+    /// API base URL; the default matches `wrangler dev`
+    #[arg(long, env = "SHORTLINK_BASE_URL", default_value = "http://localhost:8787", global = true)]
+    base_url: String,
+    /// API key (`sk_...`, see `shortlink keys create`), sent as `Authorization: Bearer <key>`
+    #[arg(long, env = "SHORTLINK_API_KEY", hide_env_values = true, global = true)]
+    api_key: Option<String>,
+    /// Print the raw JSON responses instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a short link
+    Shorten {
+        url: String,
+        /// Custom alias instead of a generated code
+        #[arg(long)]
+        alias: Option<String>,
+        /// Generate a readable code like `brave-otter-42`
+        #[arg(long, conflicts_with = "alias")]
+        words: bool,
+    },
+    /// List your links, newest first
+    List,
+    /// Change the destination or expiry of a link
+    Update {
+        code: String,
+        /// New destination URL
+        #[arg(long)]
+        url: Option<String>,
+        /// Expiry as an RFC 3339 timestamp, e.g. 2030-01-01T00:00:00Z
+        #[arg(long, conflicts_with = "no_expiry")]
+        expires_at: Option<String>,
+        /// Remove the expiry
+        #[arg(long)]
+        no_expiry: bool,
+    },
+    /// Delete a link and its analytics
+    Delete { code: String },
+    /// Show click analytics for a link
+    Analytics { code: String },
+    /// Manage your API keys
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Create a key; it is printed only once
+    Create { name: String },
+    /// List your keys, newest first
+    List,
+    /// Rename a key
+    Rename { id: String, name: String },
+    /// Revoke a key; requests using it are rejected from then on
+    Revoke { id: String },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), String> {
+    let client = Client::new(&cli.base_url, cli.api_key.clone());
+
+    let response = match &cli.command {
+        Command::Shorten { url, alias, words } => {
+            let mut body = json!({ "url": url, "custom_alias": alias });
+            if *words {
+                body["code_style"] = json!("words");
+            }
+            client.send("POST", "/api/v1/shorten", Some(&body))?
+        }
+        Command::List => client.send("GET", "/api/v1/urls", None)?,
+        Command::Update { code, url, expires_at, no_expiry } => {
+            if url.is_none() && expires_at.is_none() && !no_expiry {
+                return Err("nothing to update; pass --url, --expires-at or --no-expiry".to_string());
+            }
+            let mut body = json!({});
+            if let Some(url) = url {
+                body["url"] = json!(url);
+            }
+            if let Some(expires_at) = expires_at {
+                body["expires_at"] = json!(expires_at);
+            } else if *no_expiry {
+                body["expires_at"] = json!("");
+            }
+            client.send("PATCH", &format!("/api/v1/urls/{}", code), Some(&body))?
+        }
+        Command::Delete { code } => client.send("DELETE", &format!("/api/v1/urls/{}", code), None)?,
+        Command::Analytics { code } => client.send("GET", &format!("/api/v1/analytics/{}", code), None)?,
+        Command::Keys { command } => match command {
+            KeysCommand::Create { name } => client.send("POST", "/api/v1/keys", Some(&json!({ "name": name })))?,
+            KeysCommand::List => client.send("GET", "/api/v1/keys", None)?,
+            KeysCommand::Rename { id, name } => client.send("PATCH", &format!("/api/v1/keys/{}", id), Some(&json!({ "name": name })))?,
+            KeysCommand::Revoke { id } => client.send("DELETE", &format!("/api/v1/keys/{}", id), None)?,
+        },
+    };
+
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&response).unwrap_or_default());
+        return Ok(());
+    }
+
+    match &cli.command {
+        Command::Shorten { .. } => {
+            let created: ShortenResponse = parse(response)?;
+            println!("{}", created.short_url);
+            println!("  -> {}", created.original_url);
+        }
+        Command::List => {
+            let links: Vec<Url> = parse(response)?;
+            let rows: Vec<Vec<String>> = links
+                .iter()
+                .map(|l| {
+                    vec![
+                        l.short_code.clone(),
+                        l.clicks.to_string(),
+                        l.created_at.chars().take(10).collect(),
+                        l.original_url.clone(),
+                    ]
+                })
+                .collect();
+            print!("{}", table(&["CODE", "CLICKS", "CREATED", "DESTINATION"], &rows));
+        }
+        Command::Update { code, .. } => {
+            let link: Url = parse(response)?;
+            println!("Updated {} -> {}", code, link.original_url);
+            if let Some(expires_at) = link.expires_at {
+                println!("  expires {}", expires_at);
+            }
+        }
+        Command::Delete { code } => println!("Deleted {}", code),
+        Command::Analytics { .. } => print_analytics(&parse(response)?),
+        Command::Keys { command } => match command {
+            KeysCommand::Create { .. } => {
+                let created: CreatedApiKey = parse(response)?;
+                println!("{}", created.key);
+                println!("  id {}; store it now, it won't be shown again", created.api_key.id);
+            }
+            KeysCommand::List => {
+                let keys: Vec<ApiKeyInfo> = parse(response)?;
+                let rows: Vec<Vec<String>> = keys
+                    .iter()
+                    .map(|k| {
+                        vec![
+                            k.id.clone(),
+                            k.prefix.clone(),
+                            k.name.clone(),
+                            k.last_used_at.as_deref().map_or("never".to_string(), |t| t.chars().take(10).collect()),
+                        ]
+                    })
+                    .collect();
+                print!("{}", table(&["ID", "PREFIX", "NAME", "LAST USED"], &rows));
+            }
+            KeysCommand::Rename { id, name } => println!("Renamed {} to {}", id, name),
+            KeysCommand::Revoke { id } => println!("Revoked {}", id),
+        },
+    }
+    Ok(())
+}
+
+fn parse<T: serde::de::DeserializeOwned>(value: Value) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|e| format!("unexpected response: {}", e))
+}
+
+struct Client {
+    agent: ureq::Agent,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Client {
+    fn new(base_url: &str, api_key: Option<String>) -> Self {
+        // Error responses still carry a useful body, so read them instead of failing early
+        let agent = ureq::Agent::config_builder().http_status_as_error(false).build().into();
+        Self {
+            agent,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    fn send(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, String> {
+        let url = format!("{}{}", self.base_url, path);
+        let auth = self.api_key.as_ref().map(|key| format!("Bearer {}", key));
+
+        let result = match method {
+            "GET" | "DELETE" => {
+                let mut req = if method == "GET" { self.agent.get(&url) } else { self.agent.delete(&url) };
+                if let Some(auth) = &auth {
+                    req = req.header("Authorization", auth);
+                }
+                req.call()
+            }
+            _ => {
+                let mut req = if method == "POST" { self.agent.post(&url) } else { self.agent.patch(&url) };
+                if let Some(auth) = &auth {
+                    req = req.header("Authorization", auth);
+                }
+                req.send_json(body.unwrap_or(&Value::Null))
+            }
+        };
+
+        let mut response = result.map_err(|e| format!("{} {}: {}", method, url, e))?;
+        let status = response.status().as_u16();
+        let text = response.body_mut().read_to_string().map_err(|e| e.to_string())?;
+        read_response(status, &text)
+    }
+}
+
+// Turn a response into JSON, or into an error message if the API reported one
+fn read_response(status: u16, body: &str) -> Result<Value, String> {
+    if status == 204 || body.trim().is_empty() && status < 400 {
+        return Ok(Value::Null);
+    }
+
+    let value: Value = match serde_json::from_str(body) {
+        Ok(v) => v,
+        Err(_) if status >= 400 => return Err(format!("HTTP {}: {}", status, body.trim())),
+        Err(e) => return Err(format!("unexpected response: {}", e)),
+    };
+
+    // Errors carry an `error` message; servers before status codes were added sent them with a 200
+    match value.get("error").and_then(Value::as_str) {
+        Some(error) => Err(error.to_string()),
+        None if status >= 400 => Err(format!("HTTP {}", status)),
+        None => Ok(value),
+    }
+}
+
+fn print_analytics(stats: &AnalyticsResponse) {
+    println!("Total clicks: {}", stats.total_clicks);
+
+    let row = |label: &Option<String>, count: i64| vec![label.as_deref().unwrap_or("-").to_string(), count.to_string()];
+    let sections: [(&str, Vec<Vec<String>>); 5] = [
+        ("Countries", stats.countries.iter().map(|c| row(&c.country, c.count)).collect()),
+        ("Devices", stats.devices.iter().map(|d| row(&d.device_type, d.count)).collect()),
+        ("Browsers", stats.browsers.iter().map(|b| row(&b.browser, b.count)).collect()),
+        ("Referrers", stats.referrers.iter().map(|r| row(&r.referrer, r.count)).collect()),
+        ("Last 30 days", stats.timeline.iter().map(|d| vec![d.date.clone(), d.count.to_string()]).collect()),
+    ];
+
+    for (title, rows) in sections {
+        if rows.is_empty() {
+            continue;
+        }
+        println!();
+        print!("{}", table(&[title, "CLICKS"], &rows));
+    }
+}
+
+/// Left-aligned columns separated by two spaces
+fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", padded.join("  ").trim_end())
+    };
+
+    let mut out = line(headers.to_vec());
+    for row in rows {
+        out.push_str(&line(row.iter().map(String::as_str).collect()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let rows = vec![
+            vec!["abc".to_string(), "12".to_string()],
+            vec!["brave-otter-42".to_string(), "3".to_string()],
+        ];
+        assert_eq!(
+            table(&["CODE", "CLICKS"], &rows),
+            "CODE            CLICKS\nabc             12\nbrave-otter-42  3\n"
+        );
+    }
+
+    #[test]
+    fn test_read_response() {
+        assert_eq!(read_response(200, r#"{"short_code": "abc"}"#).unwrap()["short_code"], "abc");
+        assert_eq!(read_response(204, "").unwrap(), Value::Null);
+        assert_eq!(read_response(200, r#"{"error": "Custom alias already taken"}"#).unwrap_err(), "Custom alias already taken");
+        assert_eq!(read_response(404, "URL not found").unwrap_err(), "HTTP 404: URL not found");
+    }
 }
@@ -1,16 +1,20 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+use crate::codegen::CodeStyle;
+
+/// One row of the `users` table, recorded on sign-in
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
+    /// Google account id
     pub id: String,
-    pub email: String,
-    pub api_key: String,
+    pub email: Option<String>,
     pub subscription_tier: SubscriptionTier,
     pub created_at: String,
     pub stripe_customer_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum SubscriptionTier {
     Free,
@@ -19,6 +23,21 @@ pub enum SubscriptionTier {
 }
 
 impl SubscriptionTier {
+    /// Name as stored in the `users` table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionTier::Free => "free",
+            SubscriptionTier::Pro => "pro",
+            SubscriptionTier::Business => "business",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [SubscriptionTier::Free, SubscriptionTier::Pro, SubscriptionTier::Business]
+            .into_iter()
+            .find(|tier| tier.as_str() == name)
+    }
+
     pub fn urls_per_month(&self) -> i32 {
         match self {
             SubscriptionTier::Free => 100,
@@ -42,9 +61,18 @@ impl SubscriptionTier {
     pub fn has_api_access(&self) -> bool {
         matches!(self, SubscriptionTier::Pro | SubscriptionTier::Business)
     }
+
+    /// Minimum and maximum custom alias length, in characters
+    pub fn alias_length(&self) -> (usize, usize) {
+        match self {
+            SubscriptionTier::Free => (3, 20),
+            SubscriptionTier::Pro => (3, 32),
+            SubscriptionTier::Business => (2, 64),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Url {
     pub id: String,
     pub short_code: String,
@@ -53,60 +81,195 @@ pub struct Url {
     pub created_at: String,
     pub expires_at: Option<String>,
     pub clicks: i32,
+    // Scraped from the destination page after creation
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub favicon_url: Option<String>,
+    // Maintained by the scheduled health checker
+    pub last_status: Option<i32>,
+    pub last_checked_at: Option<String>,
+    #[serde(default)]
+    pub consecutive_failures: i32,
+    /// Set once the destination has failed enough checks in a row
+    pub broken_since: Option<String>,
 }
 
+/// One row of the `clicks` table
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Click {
     pub id: String,
-    pub url_id: String,
+    pub short_code: String,
     pub clicked_at: String,
-    pub ip_address: String,
-    pub user_agent: String,
-    pub referer: Option<String>,
     pub country: Option<String>,
+    pub city: Option<String>,
+    pub device_type: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub referrer: Option<String>,
+    /// MD5 of the visitor's IP; the address itself is never stored
+    pub ip_hash: Option<String>,
+}
+
+/// One row of the `sessions` table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub created_at: String,
+    pub expires_at: String,
+    /// Set on logout; a revoked session's tokens are rejected even before they expire
+    pub revoked_at: Option<String>,
+}
+
+/// Body of `POST /api/v1/auth/session`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SessionRequest {
+    /// Google ID token from Google Sign-In
+    pub id_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SessionResponse {
+    /// Send as `Authorization: Bearer <token>`
+    pub token: String,
+    pub expires_at: String,
+}
+
+/// One row of the `api_keys` table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// Hex SHA-256 of the key; the key itself is only shown once, at creation
+    pub key_hash: String,
+    pub prefix: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Body of `POST /api/v1/keys` and `PATCH /api/v1/keys/:id`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApiKeyRequest {
+    pub name: String,
+}
+
+/// An API key as listed to its owner, without the secret
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    /// First characters of the key, e.g. `sk_1a2b3c4d`
+    pub prefix: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+impl From<&ApiKey> for ApiKeyInfo {
+    fn from(key: &ApiKey) -> Self {
+        ApiKeyInfo {
+            id: key.id.clone(),
+            name: key.name.clone(),
+            prefix: key.prefix.clone(),
+            created_at: key.created_at.clone(),
+            last_used_at: key.last_used_at.clone(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// Send as `Authorization: Bearer <key>`. Not shown again.
+    pub key: String,
+    pub api_key: ApiKeyInfo,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct ShortenRequest {
     pub url: String,
     pub custom_alias: Option<String>,
+    /// Format of the generated code when no custom alias is given
+    pub code_style: Option<CodeStyle>,
+}
+
+/// Body of `PATCH /api/urls/:code`; omitted fields are left unchanged
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateLinkRequest {
+    pub url: Option<String>,
+    /// RFC 3339 timestamp, or an empty string to remove the expiry
+    pub expires_at: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ShortenResponse {
     pub short_url: String,
     pub short_code: String,
     pub original_url: String,
 }
 
-#[derive(Debug, Serialize)]
+/// Body of every error response, sent with a 4xx/5xx status
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorResponse {
+    /// Human-readable message
     pub error: String,
+    /// Stable machine-readable code, e.g. `ALIAS_TAKEN`
+    pub code: String,
+    /// Free alternatives when the code is `ALIAS_TAKEN`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AliasAvailability {
+    pub alias: String,
+    pub available: bool,
+    /// Why the alias can't be used, if it can't
+    pub reason: Option<String>,
+    pub suggestions: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Body of `GET /api/analytics/:code`. Missing values (e.g. an unknown country) are `null`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct AnalyticsResponse {
-    pub url: Url,
-    pub total_clicks: i32,
-    pub clicks_by_date: Vec<DateCount>,
-    pub clicks_by_country: Vec<CountryCount>,
-    pub clicks_by_referer: Vec<RefererCount>,
+    pub total_clicks: i64,
+    /// Top 10 countries, most clicks first
+    pub countries: Vec<CountryCount>,
+    pub devices: Vec<DeviceCount>,
+    /// Top 10 browsers, most clicks first
+    pub browsers: Vec<BrowserCount>,
+    /// Clicks per day over the last 30 days, oldest first
+    pub timeline: Vec<DateCount>,
+    /// Top 10 referrers, most clicks first
+    pub referrers: Vec<ReferrerCount>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct DateCount {
     pub date: String,
-    pub count: i32,
+    pub count: i64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct CountryCount {
-    pub country: String,
-    pub count: i32,
+    pub country: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DeviceCount {
+    pub device_type: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BrowserCount {
+    pub browser: Option<String>,
+    pub count: i64,
 }
 
-#[derive(Debug, Serialize)]
-pub struct RefererCount {
-    pub referer: String,
-    pub count: i32,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReferrerCount {
+    pub referrer: Option<String>,
+    pub count: i64,
 }
@@ -0,0 +1,226 @@
+use serde_json::Value;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{OpenApi as Document, Server};
+use utoipa::{Modify, OpenApi};
+
+/// The OpenAPI document for the public API. Paths come from the route descriptions
+/// below, schemas from the models' `ToSchema` derives, so both follow the code.
+pub fn document(base_url: &str) -> Value {
+    let mut doc = ApiDoc::openapi();
+    doc.servers = Some(vec![Server::new(base_url)]);
+    // Taken from Cargo.toml, which has no license
+    doc.info.license = None;
+    // The document is built from our own types, so it always serializes
+    serde_json::to_value(doc).unwrap_or_default()
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "URL Shortener API",
+        description = "Errors use one envelope: `error` is a human-readable message, `code` a stable machine-readable code such as `ALIAS_TAKEN`. Unversioned `/api/...` routes are deprecated aliases of `/api/v1/...` and send a `Deprecation` header.",
+    ),
+    paths(
+        routes::create_session,
+        routes::end_session,
+        routes::create_api_key,
+        routes::list_api_keys,
+        routes::rename_api_key,
+        routes::revoke_api_key,
+        routes::shorten,
+        routes::list_links,
+        routes::update_link,
+        routes::delete_link,
+        routes::alias_available,
+        routes::analytics,
+        routes::redirect,
+    ),
+    modifiers(&BearerAuth),
+    security(("bearer" = []), ()),
+)]
+struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, doc: &mut Document) {
+        let scheme = HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .description(Some("Session token from `POST /api/v1/auth/session`, or an `sk_...` API key"))
+            .build();
+        doc.components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme("bearer", SecurityScheme::Http(scheme));
+    }
+}
+
+// One description per route, held against `server::ROUTES` by `test_documented_paths_are_routed`
+#[allow(dead_code)]
+mod routes {
+    use crate::models::{
+        AliasAvailability, AnalyticsResponse, ApiKeyInfo, ApiKeyRequest, CreatedApiKey, ErrorResponse, SessionRequest, SessionResponse,
+        ShortenRequest, ShortenResponse, UpdateLinkRequest, Url,
+    };
+
+    /// Exchange a Google ID token for a session token
+    #[utoipa::path(tag = "Sessions", post, path = "/api/v1/auth/session", request_body = SessionRequest, responses(
+        (status = 200, description = "A session token to send as `Authorization: Bearer`", body = SessionResponse),
+        (status = 400, description = "Invalid body", body = ErrorResponse),
+        (status = 401, description = "Google ID token rejected", body = ErrorResponse),
+    ))]
+    fn create_session() {}
+
+    /// Log out, revoking the session token sent as `Authorization: Bearer`
+    #[utoipa::path(tag = "Sessions", delete, path = "/api/v1/auth/session", responses(
+        (status = 204, description = "Revoked"),
+        (status = 401, description = "Missing or invalid session token", body = ErrorResponse),
+    ))]
+    fn end_session() {}
+
+    /// Create an API key
+    #[utoipa::path(tag = "API keys", post, path = "/api/v1/keys", request_body = ApiKeyRequest, responses(
+        (status = 200, description = "The key, shown only this once, and its details", body = CreatedApiKey),
+        (status = 400, description = "Invalid body or name", body = ErrorResponse),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+    ))]
+    fn create_api_key() {}
+
+    /// The caller's API keys, newest first, without the secrets
+    #[utoipa::path(tag = "API keys", get, path = "/api/v1/keys", responses(
+        (status = 200, description = "Keys that haven't been revoked", body = Vec<ApiKeyInfo>),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+    ))]
+    fn list_api_keys() {}
+
+    /// Rename one of the caller's API keys
+    #[utoipa::path(tag = "API keys", patch, path = "/api/v1/keys/{id}", params(("id" = String, Path, description = "API key id")), request_body = ApiKeyRequest, responses(
+        (status = 200, description = "The renamed key", body = ApiKeyInfo),
+        (status = 400, description = "Invalid body or name", body = ErrorResponse),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+        (status = 404, description = "No such key owned by the caller", body = ErrorResponse),
+    ))]
+    fn rename_api_key() {}
+
+    /// Revoke one of the caller's API keys
+    #[utoipa::path(tag = "API keys", delete, path = "/api/v1/keys/{id}", params(("id" = String, Path, description = "API key id")), responses(
+        (status = 204, description = "Revoked"),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+        (status = 404, description = "No such key owned by the caller", body = ErrorResponse),
+    ))]
+    fn revoke_api_key() {}
+
+    /// Create a short link
+    #[utoipa::path(tag = "Links", post, path = "/api/v1/shorten", request_body = ShortenRequest, responses(
+        (status = 200, description = "The created link", body = ShortenResponse),
+        (status = 400, description = "Invalid body, URL, destination or alias", body = ErrorResponse),
+        (status = 401, description = "Invalid or expired token; anonymous requests need none", body = ErrorResponse),
+        (status = 403, description = "Destination is blocked", body = ErrorResponse),
+        (status = 409, description = "Alias already taken; see `suggestions`", body = ErrorResponse),
+        (status = 503, description = "No free short code; try again", body = ErrorResponse),
+    ))]
+    fn shorten() {}
+
+    /// The caller's links, newest first
+    #[utoipa::path(tag = "Links", get, path = "/api/v1/urls", responses(
+        (status = 200, description = "Up to 50 links", body = Vec<Url>),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+    ))]
+    fn list_links() {}
+
+    /// Change the destination or expiry of one of the caller's links
+    #[utoipa::path(tag = "Links", patch, path = "/api/v1/urls/{code}", params(("code" = String, Path, description = "Short code")), request_body = UpdateLinkRequest, responses(
+        (status = 200, description = "The updated link", body = Url),
+        (status = 400, description = "Invalid body, URL, destination or expiry", body = ErrorResponse),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+        (status = 403, description = "Destination is blocked", body = ErrorResponse),
+        (status = 404, description = "No such link owned by the caller", body = ErrorResponse),
+    ))]
+    fn update_link() {}
+
+    /// Delete one of the caller's links and its analytics
+    #[utoipa::path(tag = "Links", delete, path = "/api/v1/urls/{code}", params(("code" = String, Path, description = "Short code")), responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "Not authenticated", body = ErrorResponse),
+        (status = 404, description = "No such link owned by the caller", body = ErrorResponse),
+    ))]
+    fn delete_link() {}
+
+    /// Check whether a custom alias can be used
+    #[utoipa::path(tag = "Links", get, path = "/api/v1/alias/{alias}/available", params(("alias" = String, Path, description = "Requested alias")), responses(
+        (status = 200, description = "Availability, with free alternatives if taken", body = AliasAvailability),
+        (status = 401, description = "Invalid or expired token", body = ErrorResponse),
+    ))]
+    fn alias_available() {}
+
+    /// Click analytics for a link
+    #[utoipa::path(tag = "Analytics", get, path = "/api/v1/analytics/{code}", params(("code" = String, Path, description = "Short code")), responses(
+        (status = 200, description = "Click counts grouped by dimension", body = AnalyticsResponse),
+    ))]
+    fn analytics() {}
+
+    /// Follow a short link
+    #[utoipa::path(tag = "Links", get, path = "/{code}", params(("code" = String, Path, description = "Short code")), responses(
+        (status = 302, description = "Redirect to the destination", headers(("Location" = String))),
+        (status = 404, description = "Unknown link", body = ErrorResponse),
+        (status = 410, description = "Link has expired", body = ErrorResponse),
+    ))]
+    fn redirect() {}
+}
+
+/// A page that renders the document at `/api/v1/openapi.json`
+pub const DOCS_PAGE: &str = include_str!("docs.html");
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::Url;
+
+    #[test]
+    fn test_schemas_follow_models() {
+        let spec = document("https://sho.rt");
+        let schemas = &spec["components"]["schemas"];
+
+        // Every field of `Url` is present, optional fields are nullable and not required
+        let url = &schemas["Url"];
+        let serialized = serde_json::to_value(Url {
+            id: String::new(),
+            short_code: String::new(),
+            original_url: String::new(),
+            user_id: None,
+            created_at: String::new(),
+            expires_at: None,
+            clicks: 0,
+            title: None,
+            description: None,
+            favicon_url: None,
+            last_status: None,
+            last_checked_at: None,
+            consecutive_failures: 0,
+            broken_since: None,
+        })
+        .unwrap();
+        let mut fields: Vec<&String> = serialized.as_object().unwrap().keys().collect();
+        let mut properties: Vec<&String> = url["properties"].as_object().unwrap().keys().collect();
+        fields.sort();
+        properties.sort();
+        assert_eq!(fields, properties);
+        assert_eq!(url["properties"]["clicks"], json!({ "type": "integer", "format": "int32" }));
+        assert_eq!(url["properties"]["title"]["type"], json!(["string", "null"]));
+        let required = url["required"].as_array().unwrap();
+        assert!(required.contains(&json!("short_code")));
+        assert!(!required.contains(&json!("title")));
+        // Defaulted and skipped fields may be missing from a body
+        assert!(!required.contains(&json!("consecutive_failures")));
+        assert_eq!(schemas["ErrorResponse"]["required"], json!(["error", "code"]));
+
+        // Nested types become references, enums list every accepted value
+        assert_eq!(schemas["AnalyticsResponse"]["properties"]["countries"]["items"]["$ref"], "#/components/schemas/CountryCount");
+        assert_eq!(schemas["CodeStyle"]["enum"], json!(["default", "words"]));
+        assert_eq!(schemas["ShortenRequest"]["properties"]["code_style"]["oneOf"][1]["$ref"], "#/components/schemas/CodeStyle");
+        assert_eq!(schemas["ShortenRequest"]["required"], json!(["url"]));
+        assert_eq!(spec["servers"][0]["url"], "https://sho.rt");
+        assert_eq!(spec["paths"]["/api/v1/urls/{code}"]["patch"]["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/UpdateLinkRequest");
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::Serialize;
+
+use crate::store::{LinkStore, MaintenanceStore, StoreResult};
+
+/// Links created this recently are left alone: they may still be between the D1
+/// reservation and the KV write, and KV lists can lag writes by up to a minute
+pub const GRACE_PERIOD_SECS: i64 = 10 * 60;
+
+/// Short codes present in only one of the two stores
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Drift {
+    /// In KV (so the link redirects) but missing from the dashboard
+    pub missing_in_d1: Vec<String>,
+    /// In D1 (so it shows on the dashboard) but the link doesn't redirect
+    pub missing_in_kv: Vec<String>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.missing_in_d1.is_empty() && self.missing_in_kv.is_empty()
+    }
+}
+
+/// Result of one reconciliation run, stored in `reconcile_runs`
+#[derive(Debug, Serialize)]
+pub struct ReconcileReport {
+    pub ran_at: String,
+    pub kv_keys: usize,
+    pub d1_rows: usize,
+    #[serde(flatten)]
+    pub drift: Drift,
+    /// KV-only codes whose dashboard row was restored (only in repair mode)
+    pub repaired: Vec<String>,
+    /// D1-only codes whose rows were deleted (only in repair mode)
+    pub deleted: Vec<String>,
+    /// Codes left alone because they are within the grace period
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Whether the job fixes drift or only reports it, from the `RECONCILE_MODE` var
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReconcileMode {
+    Report,
+    Repair,
+}
+
+impl ReconcileMode {
+    pub fn parse(value: &str) -> Self {
+        if value.trim().eq_ignore_ascii_case("repair") {
+            ReconcileMode::Repair
+        } else {
+            ReconcileMode::Report
+        }
+    }
+}
+
+/// Compare KV keys against D1 short codes. Keys containing `:` are internal and never short links.
+pub fn diff(kv_keys: &[String], d1_codes: &[String]) -> Drift {
+    let kv: HashSet<&str> = kv_keys
+        .iter()
+        .map(String::as_str)
+        .filter(|k| !k.contains(':'))
+        .collect();
+    let d1: HashSet<&str> = d1_codes.iter().map(String::as_str).collect();
+
+    let mut missing_in_d1: Vec<String> = kv.difference(&d1).map(|s| s.to_string()).collect();
+    let mut missing_in_kv: Vec<String> = d1.difference(&kv).map(|s| s.to_string()).collect();
+    missing_in_d1.sort();
+    missing_in_kv.sort();
+
+    Drift {
+        missing_in_d1,
+        missing_in_kv,
+    }
+}
+
+/// Find codes that are only published or only reserved, fix them in repair mode,
+/// and save the report. Links within the grace period are skipped.
+pub async fn run<S: LinkStore + MaintenanceStore>(store: &S, mode: ReconcileMode, now: DateTime<Utc>) -> StoreResult<ReconcileReport> {
+    let published = store.published_codes().await?;
+    let reserved = store.reserved_codes().await?;
+
+    let mut report = ReconcileReport {
+        ran_at: now.to_rfc3339(),
+        kv_keys: published.len(),
+        d1_rows: reserved.len(),
+        drift: diff(&published, &reserved),
+        repaired: Vec::new(),
+        deleted: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    if mode == ReconcileMode::Repair {
+        // Published only: the link works, so add the missing dashboard row
+        for code in &report.drift.missing_in_d1 {
+            match store.get_link(code).await? {
+                Some(url) if !past_grace_period(&url.created_at, now) => report.skipped.push(code.clone()),
+                Some(url) if store.reserve_link(&url).await.unwrap_or(false) => report.repaired.push(code.clone()),
+                _ => report.failed.push(code.clone()),
+            }
+        }
+
+        // Reserved only: deletes unpublish first, so this is usually a delete that didn't finish.
+        // Republishing would bring the link back; finish the delete instead.
+        for code in &report.drift.missing_in_kv {
+            match store.reserved_link(code).await? {
+                Some(url) if !past_grace_period(&url.created_at, now) => report.skipped.push(code.clone()),
+                Some(url) if store.delete_link(&url).await.is_ok() => report.deleted.push(code.clone()),
+                _ => report.failed.push(code.clone()),
+            }
+        }
+    }
+
+    store.save_reconcile_report(&report).await?;
+    Ok(report)
+}
+
+/// Whether a link created at `created_at` is past the grace period at `now`. Rows created
+/// by SQLite's `CURRENT_TIMESTAMP` have no offset; unreadable timestamps count as old.
+pub fn past_grace_period(created_at: &str, now: DateTime<Utc>) -> bool {
+    let created = DateTime::parse_from_rfc3339(created_at)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(created_at, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc()));
+    match created {
+        Ok(created) => now - created >= Duration::seconds(GRACE_PERIOD_SECS),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let drift = diff(
+            &strings(&["abc", "only-kv", "sys:state", "both"]),
+            &strings(&["both", "abc", "only-d1"]),
+        );
+
+        assert_eq!(drift.missing_in_d1, strings(&["only-kv"]));
+        assert_eq!(drift.missing_in_kv, strings(&["only-d1"]));
+        assert!(!drift.is_empty());
+        assert!(diff(&strings(&["a"]), &strings(&["a"])).is_empty());
+    }
+
+    #[test]
+    fn test_past_grace_period() {
+        let now = DateTime::parse_from_rfc3339("2030-01-01T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        assert!(!past_grace_period("2030-01-01T11:55:00Z", now));
+        assert!(!past_grace_period("2030-01-01T13:55:00+02:00", now));
+        assert!(past_grace_period("2030-01-01T11:50:00Z", now));
+        assert!(past_grace_period("2030-01-01 11:00:00", now));
+        assert!(!past_grace_period("2030-01-01 11:59:00", now));
+        assert!(past_grace_period("", now));
+    }
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(ReconcileMode::parse("Repair"), ReconcileMode::Repair);
+        assert_eq!(ReconcileMode::parse("report"), ReconcileMode::Report);
+        assert_eq!(ReconcileMode::parse(""), ReconcileMode::Report);
+    }
+}
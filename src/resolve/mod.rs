@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use crate::utils::is_valid_url;
+
+/// Give up after following this many short links or redirects
+pub const MAX_HOPS: usize = 5;
+
+/// Third-party shorteners expanded when `EXPAND_SHORTENERS` is enabled
+pub const KNOWN_SHORTENERS: &[&str] = &[
+    "bit.ly", "bitly.com", "buff.ly", "cutt.ly", "goo.gl", "is.gd", "ow.ly", "rb.gy",
+    "rebrand.ly", "shorturl.at", "t.co", "t.ly", "tiny.cc", "tinyurl.com",
+];
+
+/// Where a destination URL points
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// One of our own short links
+    ShortLink(String),
+    /// Some other page on our own domains (home page, API, ...)
+    OwnDomain,
+    /// A link on a known third-party shortener
+    Shortener,
+    External,
+}
+
+/// Looks up the next hop of a destination
+#[allow(async_fn_in_trait)]
+pub trait RedirectSource {
+    /// Destination stored for one of our short codes
+    async fn short_link(&self, code: &str) -> Option<String>;
+    /// `Location` of a third-party redirect, without following it
+    async fn follow(&self, url: &str) -> Option<String>;
+}
+
+/// Follows hops through our KV namespace and the Workers `fetch` API
+#[cfg(target_arch = "wasm32")]
+pub struct WorkerRedirects {
+    pub kv: worker::kv::KvStore,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl RedirectSource for WorkerRedirects {
+    async fn short_link(&self, code: &str) -> Option<String> {
+        let data = self.kv.get(code).text().await.ok()??;
+        let url: crate::models::Url = serde_json::from_str(&data).ok()?;
+        Some(url.original_url)
+    }
+
+    async fn follow(&self, url: &str) -> Option<String> {
+        let mut init = worker::RequestInit::new();
+        init.with_method(worker::Method::Head)
+            .with_redirect(worker::RequestRedirect::Manual);
+        let req = worker::Request::new_with_init(url, &init).ok()?;
+        let resp = worker::Fetch::Request(req).send().await.ok()?;
+        if !(300..400).contains(&resp.status_code()) {
+            return None;
+        }
+        let location = resp.headers().get("Location").ok()??;
+        // Location may be relative to the shortener
+        url::Url::parse(url).ok()?.join(&location).ok().map(|u| u.to_string())
+    }
+}
+
+/// Hosts that serve our own short links: `BASE_URL` plus any extra `SHORT_DOMAINS`
+pub fn own_hosts(base_url: &str, extra: Option<&str>) -> Vec<String> {
+    url::Url::parse(base_url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+        .into_iter()
+        .chain(
+            extra
+                .unwrap_or_default()
+                .split(',')
+                .map(|h| h.trim().to_lowercase())
+                .filter(|h| !h.is_empty()),
+        )
+        .collect()
+}
+
+pub fn classify(url: &str, own_hosts: &[String]) -> Target {
+    let parsed = match url::Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return Target::External,
+    };
+    let host = parsed.host_str().unwrap_or_default().trim_end_matches('.').to_lowercase();
+
+    if own_hosts.contains(&host) {
+        let segments: Vec<&str> = parsed.path().trim_matches('/').split('/').collect();
+        return match segments.as_slice() {
+            [code] if !code.is_empty() && *code != "api" => Target::ShortLink(code.to_string()),
+            _ => Target::OwnDomain,
+        };
+    }
+
+    if KNOWN_SHORTENERS.contains(&host.as_str()) {
+        Target::Shortener
+    } else {
+        Target::External
+    }
+}
+
+/// Follow `url` through our own short links (and, if `expand` is set, third-party
+/// shorteners) to the final destination, rejecting anything that loops back to us
+pub async fn resolve_destination<S: RedirectSource>(
+    source: &S,
+    own_hosts: &[String],
+    expand: bool,
+    url: &str,
+) -> Result<String, String> {
+    let mut current = url.to_string();
+    let mut seen = HashSet::new();
+
+    for _ in 0..=MAX_HOPS {
+        if !seen.insert(current.clone()) {
+            return Err("Destination redirects back to itself".to_string());
+        }
+        // Every hop, not just the submitted URL, must stay on http(s)
+        if !is_valid_url(&current) {
+            return Err("Destination redirects to a URL that is not http:// or https://".to_string());
+        }
+
+        let next = match classify(&current, own_hosts) {
+            Target::External => return Ok(current),
+            Target::OwnDomain => return Err("Destination cannot point to this service".to_string()),
+            Target::ShortLink(code) => match source.short_link(&code).await {
+                Some(next) => next,
+                None => return Err(format!("Destination points to unknown short link '{}'", code)),
+            },
+            Target::Shortener if expand => match source.follow(&current).await {
+                Some(next) => next,
+                // Could not expand; keep the third-party link as-is
+                None => return Ok(current),
+            },
+            Target::Shortener => return Ok(current),
+        };
+        current = next;
+    }
+
+    Err("Destination has too many redirects".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::block_on;
+    use std::collections::HashMap;
+
+    struct StubRedirects {
+        links: HashMap<&'static str, &'static str>,
+        redirects: HashMap<&'static str, &'static str>,
+    }
+
+    impl RedirectSource for StubRedirects {
+        async fn short_link(&self, code: &str) -> Option<String> {
+            self.links.get(code).map(|s| s.to_string())
+        }
+
+        async fn follow(&self, url: &str) -> Option<String> {
+            self.redirects.get(url).map(|s| s.to_string())
+        }
+    }
+
+    fn hosts() -> Vec<String> {
+        own_hosts("https://s.example.dev", Some("go.example.com, "))
+    }
+
+    #[test]
+    fn test_classify() {
+        let hosts = hosts();
+        assert_eq!(classify("https://s.example.dev/abc", &hosts), Target::ShortLink("abc".to_string()));
+        assert_eq!(classify("https://GO.example.com/abc?x=1", &hosts), Target::ShortLink("abc".to_string()));
+        assert_eq!(classify("https://s.example.dev/", &hosts), Target::OwnDomain);
+        assert_eq!(classify("https://s.example.dev/api/urls", &hosts), Target::OwnDomain);
+        assert_eq!(classify("https://bit.ly/xyz", &hosts), Target::Shortener);
+        assert_eq!(classify("https://example.org/abc", &hosts), Target::External);
+    }
+
+    #[test]
+    fn test_resolve_destination() {
+        let stub = StubRedirects {
+            links: HashMap::from([
+                ("ok", "https://example.org/page"),
+                ("data", "data:text/html,<script>alert(1)</script>"),
+                ("loop1", "https://s.example.dev/loop2"),
+                ("loop2", "https://bit.ly/back"),
+            ]),
+            redirects: HashMap::from([
+                ("https://bit.ly/back", "https://go.example.com/loop1"),
+                ("https://bit.ly/out", "https://example.org/final"),
+                ("https://bit.ly/script", "javascript:alert(1)"),
+                ("https://bit.ly/file", "file:///etc/passwd"),
+            ]),
+        };
+        let hosts = hosts();
+        let resolve = |url, expand| block_on(resolve_destination(&stub, &hosts, expand, url));
+
+        assert_eq!(resolve("https://s.example.dev/ok", false).unwrap(), "https://example.org/page");
+        assert_eq!(resolve("https://bit.ly/out", true).unwrap(), "https://example.org/final");
+        assert_eq!(resolve("https://bit.ly/out", false).unwrap(), "https://bit.ly/out");
+        assert!(resolve("https://s.example.dev/loop1", true).is_err());
+        assert!(resolve("https://s.example.dev/missing", false).is_err());
+        assert!(resolve("https://s.example.dev/", false).is_err());
+        assert!(resolve("https://bit.ly/script", true).is_err());
+        assert!(resolve("https://bit.ly/file", true).is_err());
+        assert!(resolve("https://s.example.dev/data", false).is_err());
+    }
+}
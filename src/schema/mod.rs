@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// One file from `migrations/`, recorded by name in `schema_migrations` once applied
+pub struct Migration {
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every migration, oldest first. `wrangler d1 migrations apply` runs the same files on D1.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "0001_initial.sql",
+        sql: include_str!("../../migrations/0001_initial.sql"),
+    },
+    Migration {
+        name: "0002_text_ids.sql",
+        sql: include_str!("../../migrations/0002_text_ids.sql"),
+    },
+    Migration {
+        name: "0003_sessions.sql",
+        sql: include_str!("../../migrations/0003_sessions.sql"),
+    },
+    Migration {
+        name: "0004_api_keys.sql",
+        sql: include_str!("../../migrations/0004_api_keys.sql"),
+    },
+    Migration {
+        name: "0005_users.sql",
+        sql: include_str!("../../migrations/0005_users.sql"),
+    },
+];
+
+/// The bookkeeping table, defined exactly as wrangler creates it (`migrations_table` in wrangler.toml)
+pub const MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE,
+    applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+)";
+
+/// Columns the code reads or writes, per table
+pub const EXPECTED_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "urls",
+        &[
+            "id", "short_code", "original_url", "user_id", "created_at", "expires_at", "clicks", "title", "description",
+            "favicon_url", "last_status", "last_checked_at", "consecutive_failures", "broken_since",
+        ],
+    ),
+    (
+        "clicks",
+        &["id", "short_code", "clicked_at", "country", "city", "device_type", "browser", "os", "referrer", "ip_hash"],
+    ),
+    ("blocklist", &["id", "kind", "value", "reason", "created_at"]),
+    ("sequences", &["name", "value"]),
+    ("reconcile_runs", &["id", "ran_at", "report"]),
+    ("sessions", &["id", "user_id", "created_at", "expires_at", "revoked_at"]),
+    ("api_keys", &["id", "user_id", "name", "key_hash", "prefix", "created_at", "last_used_at", "revoked_at"]),
+    ("users", &["id", "email", "subscription_tier", "created_at", "stripe_customer_id"]),
+];
+
+/// How the deployed database differs from what this build expects
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SchemaReport {
+    /// False if the database was never migrated, so there is no `schema_migrations` table yet
+    pub migrations_table: bool,
+    pub applied: Vec<String>,
+    /// Known migrations that haven't been applied yet
+    pub pending: Vec<String>,
+    /// Applied migrations this build doesn't know, i.e. the database is newer than the code
+    pub unknown: Vec<String>,
+    /// `table.column` entries the code needs but the database lacks
+    pub missing_columns: Vec<String>,
+}
+
+impl SchemaReport {
+    pub fn is_current(&self) -> bool {
+        self.migrations_table && self.pending.is_empty() && self.unknown.is_empty() && self.missing_columns.is_empty()
+    }
+}
+
+/// Compare applied migration names (`None` without a migrations table) and the deployed
+/// columns (table -> column names) against this build
+pub fn check(applied: Option<&[String]>, columns: &HashMap<String, Vec<String>>) -> SchemaReport {
+    let migrations_table = applied.is_some();
+    let applied = applied.unwrap_or_default();
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a == m.name))
+        .map(|m| m.name.to_string())
+        .collect();
+    let unknown = applied
+        .iter()
+        .filter(|a| !MIGRATIONS.iter().any(|m| m.name == a.as_str()))
+        .cloned()
+        .collect();
+
+    let mut missing_columns = Vec::new();
+    for (table, expected) in EXPECTED_COLUMNS {
+        let deployed = columns.get(*table).map(Vec::as_slice).unwrap_or_default();
+        for column in *expected {
+            if !deployed.iter().any(|c| c == column) {
+                missing_columns.push(format!("{}.{}", table, column));
+            }
+        }
+    }
+
+    SchemaReport {
+        migrations_table,
+        applied: applied.to_vec(),
+        pending,
+        unknown,
+        missing_columns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn expected_columns() -> HashMap<String, Vec<String>> {
+        EXPECTED_COLUMNS
+            .iter()
+            .map(|(table, columns)| (table.to_string(), strings(columns)))
+            .collect()
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        let names: Vec<&str> = MIGRATIONS.iter().map(|m| m.name).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn test_check() {
+        let all: Vec<String> = MIGRATIONS.iter().map(|m| m.name.to_string()).collect();
+        assert!(check(Some(&all), &expected_columns()).is_current());
+
+        let report = check(None, &expected_columns());
+        assert!(!report.migrations_table);
+        assert_eq!(report.pending, all);
+        assert!(!report.is_current());
+
+        let mut columns = expected_columns();
+        columns.get_mut("urls").unwrap().retain(|c| c != "expires_at");
+        columns.remove("sequences");
+        let report = check(Some(&strings(&["0001_initial.sql", "0099_future.sql"])), &columns);
+        assert_eq!(report.pending, ["0002_text_ids.sql", "0003_sessions.sql", "0004_api_keys.sql", "0005_users.sql"]);
+        assert_eq!(report.unknown, ["0099_future.sql"]);
+        assert_eq!(report.missing_columns, ["urls.expires_at", "sequences.name", "sequences.value"]);
+        assert!(!report.is_current());
+    }
+}
@@ -0,0 +1,313 @@
+#[cfg(target_arch = "wasm32")]
+use futures_util::StreamExt;
+use url::Url;
+
+/// Only the document head is parsed, so fetchers stop reading after this many bytes
+pub const MAX_HTML_BYTES: usize = 64 * 1024;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_DESCRIPTION_LEN: usize = 500;
+
+/// Title, description and favicon scraped from a destination page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub favicon_url: Option<String>,
+}
+
+/// Source of destination HTML, so tests can swap in a stand-in server
+#[allow(async_fn_in_trait)]
+pub trait PageFetcher {
+    async fn fetch_html(&self, url: &str) -> Result<String, String>;
+}
+
+/// Fetches pages through the Workers `fetch` API
+#[cfg(target_arch = "wasm32")]
+pub struct WorkerFetcher;
+
+#[cfg(target_arch = "wasm32")]
+impl PageFetcher for WorkerFetcher {
+    async fn fetch_html(&self, url: &str) -> Result<String, String> {
+        let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+        let mut resp = worker::Fetch::Url(parsed)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = resp.status_code();
+        if !(200..300).contains(&status) {
+            return Err(format!("HTTP {}", status));
+        }
+        let content_type = resp.headers().get("Content-Type").ok().flatten();
+        if !is_html(content_type.as_deref()) {
+            return Err(format!("Not an HTML page: {}", content_type.unwrap_or_default()));
+        }
+
+        // Only the start of the page is parsed, so don't buffer the rest of a large response
+        let mut body = Vec::new();
+        let mut chunks = resp.stream().map_err(|e| e.to_string())?;
+        while body.len() < MAX_HTML_BYTES {
+            match chunks.next().await {
+                Some(chunk) => body.extend(chunk.map_err(|e| e.to_string())?),
+                None => break,
+            }
+        }
+        body.truncate(MAX_HTML_BYTES);
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// Whether a response's `Content-Type` may hold a page worth scraping. A missing header is
+/// given the benefit of the doubt.
+pub fn is_html(content_type: Option<&str>) -> bool {
+    content_type.is_none_or(|value| {
+        let mime = value.split(';').next().unwrap_or_default().trim();
+        mime.eq_ignore_ascii_case("text/html") || mime.eq_ignore_ascii_case("application/xhtml+xml")
+    })
+}
+
+/// Fetch `page_url` and extract its metadata
+pub async fn scrape<F: PageFetcher>(fetcher: &F, page_url: &str) -> Result<PageMetadata, String> {
+    let html = fetcher.fetch_html(page_url).await?;
+    Ok(parse_metadata(&html, page_url))
+}
+
+/// Extract `<title>`, meta description and favicon URL from an HTML document
+pub fn parse_metadata(html: &str, page_url: &str) -> PageMetadata {
+    let html = truncate(html, MAX_HTML_BYTES);
+    // ASCII lowercasing keeps byte offsets identical to the original
+    let lower = html.to_ascii_lowercase();
+    let head_end = lower.find("</head").unwrap_or(lower.len());
+    let (html, lower) = (&html[..head_end], &lower[..head_end]);
+
+    let title = lower.find("<title").and_then(|start| {
+        let open_end = start + lower[start..].find('>')? + 1;
+        let close = open_end + lower[open_end..].find("</title")?;
+        clean_text(&html[open_end..close], MAX_TITLE_LEN)
+    });
+
+    let mut description = None;
+    let mut og_description = None;
+    for tag in tags(html, lower, "meta") {
+        let content = match attr(tag, "content") {
+            Some(c) => c,
+            None => continue,
+        };
+        let name = attr(tag, "name").or_else(|| attr(tag, "property")).unwrap_or_default();
+        match name.to_ascii_lowercase().as_str() {
+            "description" if description.is_none() => description = clean_text(&content, MAX_DESCRIPTION_LEN),
+            "og:description" if og_description.is_none() => og_description = clean_text(&content, MAX_DESCRIPTION_LEN),
+            _ => {}
+        }
+    }
+
+    let base = Url::parse(page_url).ok();
+    let favicon_url = tags(html, lower, "link")
+        .find(|tag| {
+            attr(tag, "rel")
+                .map(|rel| rel.to_ascii_lowercase().split_whitespace().any(|r| r == "icon"))
+                .unwrap_or(false)
+        })
+        .and_then(|tag| attr(tag, "href"))
+        .and_then(|href| resolve(base.as_ref(), &href))
+        .or_else(|| resolve(base.as_ref(), "/favicon.ico"));
+
+    PageMetadata {
+        title,
+        description: description.or(og_description),
+        favicon_url,
+    }
+}
+
+// Iterate over the raw text of every `<name ...>` tag
+fn tags<'a>(html: &'a str, lower: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    let needle = format!("<{}", name);
+    let mut pos = 0;
+    std::iter::from_fn(move || loop {
+        let start = pos + lower[pos..].find(&needle)?;
+        let end = start + lower[start..].find('>')? + 1;
+        pos = end;
+        // Skip longer tag names sharing the prefix, e.g. <metadata> for <meta
+        match lower.as_bytes().get(start + needle.len()) {
+            Some(b) if b.is_ascii_whitespace() || *b == b'/' || *b == b'>' => return Some(&html[start..end]),
+            _ => continue,
+        }
+    })
+}
+
+// Read a quoted or bare attribute value from a single tag
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+    while let Some(found) = lower[search..].find(name) {
+        let start = search + found;
+        search = start + name.len();
+
+        let preceded_by_space = lower[..start].ends_with(|c: char| c.is_ascii_whitespace());
+        let rest = lower[search..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        return match value.chars().next() {
+            Some(q @ ('"' | '\'')) => value[1..].find(q).map(|end| value[1..=end].to_string()),
+            Some(_) => Some(
+                value
+                    .split(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .next()
+                    .unwrap_or_default()
+                    .trim_end_matches('/')
+                    .to_string(),
+            ),
+            None => None,
+        };
+    }
+    None
+}
+
+fn resolve(base: Option<&Url>, href: &str) -> Option<String> {
+    let resolved = match base {
+        Some(base) => base.join(href).ok()?,
+        None => Url::parse(href).ok()?,
+    };
+    match resolved.scheme() {
+        "http" | "https" => Some(resolved.to_string()),
+        _ => None,
+    }
+}
+
+// Decode common entities, collapse whitespace and cap the length
+fn clean_text(raw: &str, max_len: usize) -> Option<String> {
+    let decoded = raw
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ");
+    let text = decoded.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(max_len).collect())
+}
+
+fn truncate(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::block_on;
+    use std::collections::HashMap;
+
+    // Stand-in server that serves canned pages by URL
+    struct StaticFetcher(HashMap<&'static str, &'static str>);
+
+    impl PageFetcher for StaticFetcher {
+        async fn fetch_html(&self, url: &str) -> Result<String, String> {
+            self.0.get(url).map(|s| s.to_string()).ok_or_else(|| "HTTP 404".to_string())
+        }
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let html = r#"<html><HEAD>
+            <Title> Rust &amp; Friends
+            </title>
+            <metadata name="description" content="ignored">
+            <meta property="og:description" content="From OG">
+            <meta name="Description" content='A "quoted" page'>
+            <link rel="shortcut icon" href="/static/icon.png">
+        </head><body><title>Not this</title></body></html>"#;
+
+        let meta = parse_metadata(html, "https://example.com/blog/post");
+        assert_eq!(meta.title.as_deref(), Some("Rust & Friends"));
+        assert_eq!(meta.description.as_deref(), Some("A \"quoted\" page"));
+        assert_eq!(meta.favicon_url.as_deref(), Some("https://example.com/static/icon.png"));
+    }
+
+    #[test]
+    fn test_parse_metadata_defaults() {
+        let meta = parse_metadata("<html><body>plain</body></html>", "https://example.com/a/b");
+        assert_eq!(meta.title, None);
+        assert_eq!(meta.description, None);
+        assert_eq!(meta.favicon_url.as_deref(), Some("https://example.com/favicon.ico"));
+    }
+
+    #[test]
+    fn test_scrape_with_stand_in_fetcher() {
+        let fetcher = StaticFetcher(HashMap::from([(
+            "https://example.com/",
+            "<head><title>Example</title><meta name=description content=Short></head>",
+        )]));
+
+        let meta = block_on(scrape(&fetcher, "https://example.com/")).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Example"));
+        assert_eq!(meta.description.as_deref(), Some("Short"));
+        assert!(block_on(scrape(&fetcher, "https://missing.example/")).is_err());
+    }
+
+    #[test]
+    fn test_is_html() {
+        assert!(is_html(Some("text/html")));
+        assert!(is_html(Some("Text/HTML; charset=utf-8")));
+        assert!(is_html(Some("application/xhtml+xml")));
+        assert!(is_html(None));
+        assert!(!is_html(Some("application/pdf")));
+        assert!(!is_html(Some("image/png")));
+    }
+
+    #[test]
+    fn test_scrape_over_http() {
+        use crate::server::HttpFetcher;
+        use tiny_http::{Header, Response, Server};
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr());
+        let html: Header = "Content-Type: text/html; charset=utf-8".parse().unwrap();
+        // Far past the cap, with a title the scraper must never see
+        let big = format!("<head><title>Big</title>{}<title>Late</title>", "<!-- padding -->".repeat(MAX_HTML_BYTES));
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = match request.url() {
+                    "/page" => Response::from_string("<head><title>Local</title><link rel=icon href=/i.png></head>").with_header(html.clone()),
+                    "/moved" => Response::from_string("").with_status_code(302).with_header("Location: /page".parse::<Header>().unwrap()),
+                    "/big" => Response::from_string(big.clone()).with_header(html.clone()),
+                    "/file.pdf" => Response::from_string("%PDF-1.7").with_header("Content-Type: application/pdf".parse::<Header>().unwrap()),
+                    _ => Response::from_string("<title>Not found</title>").with_status_code(404).with_header(html.clone()),
+                };
+                // The fetcher hangs up on oversized bodies
+                let _ = request.respond(response);
+            }
+        });
+        let fetcher = HttpFetcher::allowing_loopback();
+
+        let meta = block_on(scrape(&fetcher, &format!("{}/page", base))).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Local"));
+        assert_eq!(meta.favicon_url, Some(format!("{}/i.png", base)));
+
+        // Each redirect hop goes through the same resolver
+        let meta = block_on(scrape(&fetcher, &format!("{}/moved", base))).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Local"));
+
+        let body = block_on(fetcher.fetch_html(&format!("{}/big", base))).unwrap();
+        assert_eq!(body.len(), MAX_HTML_BYTES);
+        assert_eq!(block_on(scrape(&fetcher, &format!("{}/big", base))).unwrap().title.as_deref(), Some("Big"));
+
+        let err = block_on(scrape(&fetcher, &format!("{}/file.pdf", base))).unwrap_err();
+        assert!(err.contains("Not an HTML page"), "{}", err);
+        assert!(block_on(scrape(&fetcher, &format!("{}/missing", base))).is_err());
+    }
+}
@@ -0,0 +1,708 @@
+use std::future::Future;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::mpsc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use chrono::Utc;
+use ureq::config::Config as AgentConfig;
+use ureq::http::Uri;
+use ureq::unversioned::resolver::{DefaultResolver, ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{DefaultConnector, NextTimeout};
+
+use crate::alias::decode_path_segment;
+use crate::api::{store_page_metadata, versioned_successor, ApiRequest, ApiResponse, App, Config};
+use crate::auth::{self, FetchedKeys, GoogleVerifier, KeyCache, KeySource, GOOGLE_CERTS_URL};
+use crate::error::{ApiError, ApiResult};
+use crate::health::{self, StatusChecker};
+use crate::models::Url;
+use crate::reconcile::{self, ReconcileMode};
+use crate::resolve::RedirectSource;
+use crate::scraper::{is_html, PageFetcher, MAX_HTML_BYTES};
+use crate::store::{ApiKeyStore, BlocklistStore, ClickStore, LinkStore, MaintenanceStore, SessionStore, StoreResult, UserStore};
+use crate::utils::current_timestamp;
+
+/// Resolves hosts like ureq's default resolver, but drops addresses on the host's own networks.
+/// Users choose the URLs we fetch, so without this a self-hosted server could be pointed at
+/// its own loopback, LAN or cloud metadata service. Every redirect hop is resolved through it.
+#[derive(Debug, Default)]
+pub struct PublicResolver {
+    allow_loopback: bool,
+    inner: DefaultResolver,
+}
+
+impl Resolver for PublicResolver {
+    fn resolve(&self, uri: &Uri, config: &AgentConfig, timeout: NextTimeout) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let mut allowed = self.empty();
+        for &addr in self.inner.resolve(uri, config, timeout)?.iter() {
+            if is_public(addr.ip()) || (self.allow_loopback && addr.ip().is_loopback()) {
+                allowed.push(addr);
+            }
+        }
+        if allowed.is_empty() {
+            let host = uri.host().unwrap_or_default();
+            return Err(ureq::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} resolves to a private address", host),
+            )));
+        }
+        Ok(allowed)
+    }
+}
+
+/// Whether `ip` is reachable on the public internet, rather than loopback, private (RFC 1918),
+/// shared (RFC 6598), link-local (including cloud metadata), unique-local, multicast or reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || a >= 240
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+// An agent that can only connect to public addresses
+fn public_agent(config: AgentConfig, allow_loopback: bool) -> ureq::Agent {
+    let resolver = PublicResolver {
+        allow_loopback,
+        inner: DefaultResolver::default(),
+    };
+    ureq::Agent::with_parts(config, DefaultConnector::default(), resolver)
+}
+
+/// Follows hops through our own links and plain HTTP, like `WorkerRedirects` does on Cloudflare
+pub struct HttpRedirects<S> {
+    pub links: S,
+    agent: ureq::Agent,
+}
+
+impl<S> HttpRedirects<S> {
+    pub fn new(links: S) -> Self {
+        let config = ureq::Agent::config_builder()
+            .max_redirects(0)
+            .http_status_as_error(false)
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build();
+        Self {
+            links,
+            agent: public_agent(config, false),
+        }
+    }
+}
+
+impl<S: LinkStore> RedirectSource for HttpRedirects<S> {
+    async fn short_link(&self, code: &str) -> Option<String> {
+        Some(self.links.get_link(code).await.ok()??.original_url)
+    }
+
+    async fn follow(&self, url: &str) -> Option<String> {
+        let resp = self.agent.head(url).call().ok()?;
+        if !resp.status().is_redirection() {
+            return None;
+        }
+        let location = resp.headers().get("Location")?.to_str().ok()?;
+        // Location may be relative to the shortener
+        url::Url::parse(url).ok()?.join(location).ok().map(|u| u.to_string())
+    }
+}
+
+/// Fetches destination pages with a blocking HTTP client
+pub struct HttpFetcher {
+    agent: ureq::Agent,
+}
+
+impl HttpFetcher {
+    fn new(allow_loopback: bool) -> Self {
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build();
+        Self {
+            agent: public_agent(config, allow_loopback),
+        }
+    }
+}
+
+#[cfg(test)]
+impl HttpFetcher {
+    /// For tests against a server on 127.0.0.1
+    pub(crate) fn allowing_loopback() -> Self {
+        Self::new(true)
+    }
+}
+
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl PageFetcher for HttpFetcher {
+    async fn fetch_html(&self, url: &str) -> Result<String, String> {
+        let mut resp = self.agent.get(url).call().map_err(|e| e.to_string())?;
+        let content_type = resp.headers().get("Content-Type").and_then(|v| v.to_str().ok());
+        if !is_html(content_type) {
+            return Err(format!("Not an HTML page: {}", content_type.unwrap_or_default()));
+        }
+        let mut body = Vec::new();
+        resp.body_mut()
+            .as_reader()
+            .take(MAX_HTML_BYTES as u64)
+            .read_to_end(&mut body)
+            .map_err(|e| e.to_string())?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+}
+
+/// Drive a future to completion on the current thread. Every store and client
+/// used by the server, and every fake used in tests, is blocking, so the futures
+/// are ready on the first poll.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = std::pin::pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+    }
+}
+
+// Google's keys, shared by every request
+static GOOGLE_KEYS: KeyCache = KeyCache::new();
+
+/// Fetches Google's token signing keys with a blocking HTTP client
+pub struct HttpKeySource;
+
+impl KeySource for HttpKeySource {
+    async fn fetch_keys(&self) -> Result<FetchedKeys, String> {
+        let mut resp = ureq::get(GOOGLE_CERTS_URL).call().map_err(|e| e.to_string())?;
+        let max_age = resp
+            .headers()
+            .get("Cache-Control")
+            .and_then(|v| v.to_str().ok())
+            .and_then(auth::max_age);
+        let jwks = resp.body_mut().read_json().map_err(|e| e.to_string())?;
+        Ok(FetchedKeys { jwks, max_age })
+    }
+}
+
+fn google_verifier(config: &Config) -> GoogleVerifier<'_, HttpKeySource> {
+    GoogleVerifier {
+        client_ids: &config.google_client_ids,
+        source: HttpKeySource,
+        cache: &GOOGLE_KEYS,
+    }
+}
+
+/// Dispatch one request to the same handlers the worker uses. Returns the
+/// response and, for new links, the link whose metadata should be scraped.
+/// Unversioned `/api/...` paths are served as deprecated aliases of `/api/v1`.
+pub async fn route<S, R>(app: &App<S, R>, method: &str, path: &str, req: &ApiRequest) -> ApiResult<(ApiResponse, Option<Url>)>
+where
+    S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore,
+    R: RedirectSource,
+{
+    match versioned_successor(path) {
+        Some(successor) => {
+            let (response, created) = dispatch(app, method, &successor, req).await?;
+            Ok((response.deprecated(&successor), created))
+        }
+        None => dispatch(app, method, path, req).await,
+    }
+}
+
+/// Every route `dispatch` serves, as method and path template in OpenAPI syntax. A request only
+/// reaches a handler through this table, so tests can hold it against the OpenAPI document.
+/// More specific templates come first; `/{code}` catches the rest.
+pub const ROUTES: &[(&str, &str)] = &[
+    ("POST", "/api/v1/shorten"),
+    ("POST", "/api/v1/auth/session"),
+    ("DELETE", "/api/v1/auth/session"),
+    ("POST", "/api/v1/keys"),
+    ("GET", "/api/v1/keys"),
+    ("PATCH", "/api/v1/keys/{id}"),
+    ("DELETE", "/api/v1/keys/{id}"),
+    ("GET", "/api/v1/urls"),
+    ("PATCH", "/api/v1/urls/{code}"),
+    ("DELETE", "/api/v1/urls/{code}"),
+    ("GET", "/api/v1/alias/{alias}/available"),
+    ("GET", "/api/v1/analytics/{code}"),
+    ("GET", "/api/v1/openapi.json"),
+    ("GET", "/api/v1/docs"),
+    ("GET", "/api/v1/admin/blocklist"),
+    ("POST", "/api/v1/admin/blocklist"),
+    ("DELETE", "/api/v1/admin/blocklist/{id}"),
+    ("GET", "/api/v1/admin/reconcile"),
+    ("GET", "/api/v1/admin/schema"),
+    ("GET", "/robots.txt"),
+    ("GET", "/favicon.ico"),
+    ("GET", "/"),
+    ("GET", "/{code}"),
+];
+
+// The values of `template`'s `{..}` placeholders, if `segments` match it.
+fn match_template<'a>(template: &str, segments: &[&'a str]) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = template.trim_matches('/').split('/').collect();
+    if parts.len() != segments.len() {
+        return None;
+    }
+    let mut params = Vec::new();
+    for (part, segment) in parts.iter().zip(segments) {
+        if part.starts_with('{') {
+            if segment.is_empty() {
+                return None;
+            }
+            params.push(*segment);
+        } else if part != segment {
+            return None;
+        }
+    }
+    Some(params)
+}
+
+async fn dispatch<S, R>(app: &App<S, R>, method: &str, path: &str, req: &ApiRequest) -> ApiResult<(ApiResponse, Option<Url>)>
+where
+    S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore,
+    R: RedirectSource,
+{
+    let segments: Vec<String> = path.trim_matches('/').split('/').map(decode_path_segment).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    // Preflight; the caller adds the CORS headers
+    if method == "OPTIONS" && segments.starts_with(&["api", "v1"]) {
+        return Ok((ApiResponse::no_content(), None));
+    }
+    let Some((template, params)) = ROUTES
+        .iter()
+        .filter(|(m, _)| *m == method)
+        .find_map(|(_, template)| Some((*template, match_template(template, &segments)?)))
+    else {
+        return Err(ApiError::NotFound);
+    };
+    let response = match (method, template, params.as_slice()) {
+        ("POST", "/api/v1/shorten", []) => {
+            let (response, created) = app.shorten(&signed_in(app, req).await?).await?;
+            return Ok((response, Some(created)));
+        }
+        ("POST", "/api/v1/auth/session", []) => app.create_session(&google_verifier(&app.config), req).await?,
+        ("DELETE", "/api/v1/auth/session", []) => app.end_session(req).await?,
+        ("POST", "/api/v1/keys", []) => app.create_api_key(&signed_in(app, req).await?).await?,
+        ("GET", "/api/v1/keys", []) => app.list_api_keys(&signed_in(app, req).await?).await?,
+        ("PATCH", "/api/v1/keys/{id}", [id]) => app.rename_api_key(id, &signed_in(app, req).await?).await?,
+        ("DELETE", "/api/v1/keys/{id}", [id]) => app.revoke_api_key(id, &signed_in(app, req).await?).await?,
+        ("GET", "/api/v1/urls", []) => app.list_links(&signed_in(app, req).await?).await?,
+        ("PATCH", "/api/v1/urls/{code}", [code]) => app.update_link(code, &signed_in(app, req).await?).await?,
+        ("DELETE", "/api/v1/urls/{code}", [code]) => app.delete_link(code, &signed_in(app, req).await?).await?,
+        ("GET", "/api/v1/alias/{alias}/available", [alias]) => app.alias_available(alias, &signed_in(app, req).await?).await?,
+        ("GET", "/api/v1/analytics/{code}", [code]) => app.analytics(code).await?,
+        ("GET", "/api/v1/openapi.json", []) => app.openapi(),
+        ("GET", "/api/v1/docs", []) => app.docs(),
+        ("GET", "/api/v1/admin/blocklist", []) => app.list_blocklist(req).await?,
+        ("POST", "/api/v1/admin/blocklist", []) => app.add_block_entry(req).await?,
+        ("DELETE", "/api/v1/admin/blocklist/{id}", [id]) => app.remove_block_entry(id, req).await?,
+        ("GET", "/api/v1/admin/reconcile", []) => app.reconcile_reports(req).await?,
+        ("GET", "/api/v1/admin/schema", []) => app.schema_report(req).await?,
+        ("GET", "/robots.txt", []) => app.robots(),
+        ("GET", "/favicon.ico", []) => app.favicon(),
+        ("GET", "/", []) => app.home(),
+        ("GET", "/{code}", [code]) => app.redirect(code, req).await?,
+        _ => unreachable!("{} {} is in ROUTES but has no handler", method, template),
+    };
+    Ok((response, None))
+}
+
+// `req` with its caller resolved from `Authorization`, unless the caller is already known.
+// Only routes that act for a user call this, so a stale token can't break redirects or analytics.
+async fn signed_in<S, R>(app: &App<S, R>, req: &ApiRequest) -> ApiResult<ApiRequest>
+where
+    S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore,
+    R: RedirectSource,
+{
+    if req.user_id.is_some() {
+        return Ok(req.clone());
+    }
+    let user_id = app.authenticate(req).await?;
+    Ok(req.clone().with_user(user_id))
+}
+
+/// How the server runs: request threads, and the jobs the worker runs from cron
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub addr: String,
+    pub threads: usize,
+    pub health_interval: Duration,
+    pub health_batch_size: u32,
+    pub failure_threshold: i32,
+    pub reconcile_interval: Duration,
+    pub reconcile_mode: ReconcileMode,
+}
+
+impl ServeOptions {
+    /// Job settings come from the same variables as wrangler.toml; the intervals match its crons
+    pub fn from_vars(addr: &str, threads: usize, var: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            addr: addr.to_string(),
+            threads: threads.max(1),
+            health_interval: Duration::from_secs(30 * 60),
+            health_batch_size: var("HEALTH_CHECK_BATCH_SIZE")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(health::DEFAULT_BATCH_SIZE),
+            failure_threshold: var("HEALTH_CHECK_FAILURE_THRESHOLD")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(health::DEFAULT_FAILURE_THRESHOLD),
+            reconcile_interval: Duration::from_secs(24 * 60 * 60),
+            reconcile_mode: var("RECONCILE_MODE")
+                .map(|v| ReconcileMode::parse(&v))
+                .unwrap_or(ReconcileMode::Report),
+        }
+    }
+}
+
+/// Checks destinations with a blocking HTTP client, following redirects to the final status
+pub struct HttpStatusChecker {
+    agent: ureq::Agent,
+}
+
+impl Default for HttpStatusChecker {
+    fn default() -> Self {
+        let config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build();
+        Self {
+            agent: public_agent(config, false),
+        }
+    }
+}
+
+impl StatusChecker for HttpStatusChecker {
+    async fn check_status(&self, url: &str) -> Option<u16> {
+        // Try a cheap HEAD first; some servers only implement GET
+        let status = self.agent.head(url).call().ok()?.status().as_u16();
+        match status {
+            405 | 501 => self.agent.get(url).call().ok().map(|resp| resp.status().as_u16()),
+            status => Some(status),
+        }
+    }
+}
+
+/// Serve the API until the process exits. `open` is called once per thread, before
+/// listening, since SQLite connections can't be shared: once for each request
+/// thread, once for the metadata scraper and once for each scheduled job.
+pub fn serve<S, R, F, C>(open: impl Fn() -> StoreResult<App<S, R>>, fetcher: &F, checker: &C, options: &ServeOptions) -> std::io::Result<()>
+where
+    S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore + Send,
+    R: RedirectSource + Send,
+    F: PageFetcher + Sync,
+    C: StatusChecker + Sync,
+{
+    let open = || open().map_err(|e| std::io::Error::other(e.to_string()));
+    let apps = (0..options.threads).map(|_| open()).collect::<std::io::Result<Vec<_>>>()?;
+    let (scraper, health_app, reconcile_app) = (open()?, open()?, open()?);
+
+    let server = tiny_http::Server::http(&options.addr).map_err(std::io::Error::other)?;
+    println!("Listening on http://{} with {} threads", options.addr, options.threads);
+
+    let (scrape, scrape_queue) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for app in apps {
+            let (server, scrape) = (&server, scrape.clone());
+            scope.spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(&app, request, &scrape);
+                }
+            });
+        }
+
+        // Scrape title and favicon off the request threads
+        scope.spawn(move || {
+            for url in scrape_queue {
+                if let Err(e) = block_on(store_page_metadata(&scraper.store, fetcher, url)) {
+                    eprintln!("Metadata scrape failed: {}", e);
+                }
+            }
+        });
+
+        // The scheduled jobs, on the same intervals as the worker's crons
+        scope.spawn(move || loop {
+            std::thread::sleep(options.health_interval);
+            let now = current_timestamp();
+            match block_on(health::run(&health_app.store, checker, options.health_batch_size, options.failure_threshold, &now)) {
+                Ok(checked) => println!("Health check: {} links checked", checked),
+                Err(e) => eprintln!("Health check failed: {}", e),
+            }
+        });
+
+        scope.spawn(move || loop {
+            std::thread::sleep(options.reconcile_interval);
+            if let Err(e) = block_on(reconcile::run(&reconcile_app.store, options.reconcile_mode, Utc::now())) {
+                eprintln!("Reconciliation failed: {}", e);
+            }
+        });
+    });
+
+    Ok(())
+}
+
+/// Largest request body accepted; every API body is a small JSON object
+pub const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+// Read at most `MAX_BODY_BYTES`, so a client can't make a thread buffer an unbounded body
+fn read_body(reader: impl Read) -> ApiResult<String> {
+    let mut body = String::new();
+    reader
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .map_err(|_| ApiError::InvalidBody)?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(ApiError::BodyTooLarge);
+    }
+    Ok(body)
+}
+
+// Answer one request, queueing new links for scraping
+fn handle<S, R>(app: &App<S, R>, mut request: tiny_http::Request, scrape: &mpsc::Sender<Url>)
+where
+    S: LinkStore + ClickStore + SessionStore + ApiKeyStore + UserStore + BlocklistStore + MaintenanceStore,
+    R: RedirectSource,
+{
+    let mut api_req = ApiRequest::new();
+    for header in request.headers() {
+        api_req = api_req.with_header(header.field.as_str().as_str(), header.value.as_str());
+    }
+    let method = request.method().as_str().to_uppercase();
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let origin = api_req.header("Origin").map(String::from);
+
+    let result = read_body(request.as_reader()).and_then(|body| block_on(route(app, &method, &path, &api_req.with_body(body))));
+    let (mut response, created) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            if e.is_server_error() {
+                eprintln!("{} {}: {}", method, path, e);
+            }
+            (e.into(), None)
+        }
+    };
+    for (name, value) in app.config.cors.headers(&method, &path, origin.as_deref()) {
+        response.headers.push((name.to_string(), value));
+    }
+
+    let mut reply = tiny_http::Response::from_string(response.body).with_status_code(response.status);
+    for (name, value) in &response.headers {
+        if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            reply.add_header(header);
+        }
+    }
+    if let Err(e) = request.respond(reply) {
+        eprintln!("{} {}: {}", method, path, e);
+    }
+
+    if let Some(url) = created {
+        // Only fails if the scraper thread is gone, and then there's nobody to tell
+        let _ = scrape.send(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Config;
+    use crate::store::SqliteStore;
+
+    #[test]
+    fn test_routes_against_sqlite() {
+        let app = App {
+            store: SqliteStore::open_in_memory().unwrap(),
+            redirects: HttpRedirects::new(SqliteStore::open_in_memory().unwrap()),
+            config: Config::from_vars(|name| (name == "BASE_URL").then(|| "http://localhost:8787".to_string())),
+        };
+        let send = |method: &str, path: &str, req: ApiRequest| {
+            block_on(route(&app, method, path, &req)).unwrap_or_else(|e| (e.into(), None))
+        };
+        let alice = || ApiRequest::new().with_user(Some("alice".to_string()));
+
+        let (response, created) = send("POST", "/api/v1/shorten", alice().with_body(r#"{"url": "https://example.com", "custom_alias": "promo"}"#));
+        assert_eq!(response.status, 200, "{}", response.body);
+        assert_eq!(created.expect(&response.body).short_code, "promo");
+
+        let (response, _) = send("GET", "/promo", ApiRequest::new().with_header("CF-IPCountry", "PL"));
+        assert_eq!(response.status, 302);
+        assert_eq!(response.header("Location"), Some("https://example.com/"));
+
+        let (response, _) = send("GET", "/api/v1/analytics/promo", ApiRequest::new());
+        let stats: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(stats["total_clicks"], 1);
+        assert_eq!(stats["countries"][0]["country"], "PL");
+        assert_eq!(stats["timeline"][0]["count"], 1);
+
+        let (response, _) = send("GET", "/api/v1/urls", alice());
+        let links: Vec<Url> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(links[0].clicks, 1);
+        assert_eq!(response.header("Deprecation"), None);
+
+        // The unversioned route still works, but points clients at its replacement
+        let (legacy, _) = send("GET", "/api/urls", alice());
+        assert_eq!(legacy.body, response.body);
+        assert_eq!(legacy.header("Deprecation"), Some("true"));
+        assert_eq!(legacy.header("Link"), Some(r#"</api/v1/urls>; rel="successor-version""#));
+
+        let (response, _) = send("PATCH", "/api/v1/urls/promo", alice().with_body(r#"{"expires_at": "2000-01-01T00:00:00Z"}"#));
+        assert_eq!(response.status, 200, "{}", response.body);
+        assert_eq!(send("GET", "/promo", ApiRequest::new()).0.status, 410);
+
+        assert_eq!(send("DELETE", "/api/v1/urls/promo", alice()).0.status, 204);
+        assert_eq!(send("GET", "/promo", ApiRequest::new()).0.status, 404);
+        assert_eq!(send("GET", "/api/unknown", ApiRequest::new()).0.status, 404);
+        assert_eq!(send("OPTIONS", "/api/v1/analytics/promo", ApiRequest::new()).0.status, 204);
+    }
+
+    #[test]
+    fn test_auth_only_where_needed() {
+        let app = App {
+            store: SqliteStore::open_in_memory().unwrap(),
+            redirects: HttpRedirects::new(SqliteStore::open_in_memory().unwrap()),
+            config: Config::from_vars(|name| match name {
+                "ADMIN_TOKEN" => Some("admin-secret".to_string()),
+                "JWT_SECRET" => Some("test-secret".to_string()),
+                _ => None,
+            }),
+        };
+        let send = |method: &str, path: &str, req: ApiRequest| {
+            block_on(route(&app, method, path, &req)).unwrap_or_else(|e| (e.into(), None))
+        };
+        let stale = || ApiRequest::new().with_header("Authorization", "Bearer expired");
+        let admin = || ApiRequest::new().with_header("X-Admin-Token", "admin-secret");
+
+        send("POST", "/api/v1/shorten", ApiRequest::new().with_body(r#"{"url": "https://example.com", "custom_alias": "promo"}"#));
+
+        // A leftover token must not break public routes
+        assert_eq!(send("GET", "/promo", stale()).0.status, 302);
+        assert_eq!(send("GET", "/api/v1/analytics/promo", stale()).0.status, 200);
+        assert_eq!(send("GET", "/api/v1/urls", stale()).0.status, 401);
+
+        assert_eq!(send("GET", "/robots.txt", stale()).0.body, "User-agent: *\nDisallow: /api/\n");
+        assert_eq!(send("GET", "/favicon.ico", ApiRequest::new()).0.header("Content-Type"), Some("image/svg+xml"));
+        assert_eq!(send("GET", "/", ApiRequest::new()).0.status, 302);
+
+        assert_eq!(send("GET", "/api/v1/admin/blocklist", ApiRequest::new()).0.status, 403);
+        let (response, _) = send("POST", "/api/v1/admin/blocklist", admin().with_body(r#"{"kind": "domain", "value": "example.com"}"#));
+        assert_eq!(response.status, 201, "{}", response.body);
+        let entry: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        let path = format!("/api/v1/admin/blocklist/{}", entry["id"].as_str().unwrap());
+        assert_eq!(send("DELETE", &path, admin()).0.status, 204);
+
+        assert_eq!(send("GET", "/api/v1/admin/schema", admin()).0.status, 200);
+        assert_eq!(send("GET", "/api/v1/admin/reconcile", admin()).0.body, "[]");
+    }
+
+    #[test]
+    fn test_refuses_private_destinations() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+
+        // A live listener on 127.0.0.1 is still never contacted
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr());
+        let err = block_on(HttpFetcher::default().fetch_html(&url)).unwrap_err();
+        assert!(err.contains("private address"), "{}", err);
+        assert_eq!(block_on(HttpStatusChecker::default().check_status(&url)), None);
+        let redirects = HttpRedirects::new(SqliteStore::open_in_memory().unwrap());
+        assert_eq!(block_on(redirects.follow(&url)), None);
+        assert!(server.try_recv().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_body_limit() {
+        let limit = MAX_BODY_BYTES as usize;
+        assert_eq!(read_body("a".repeat(limit).as_bytes()).unwrap().len(), limit);
+        let err = read_body("a".repeat(limit + 1).as_bytes()).unwrap_err();
+        assert_eq!(err, ApiError::BodyTooLarge);
+        assert_eq!(err.status(), 413);
+    }
+
+    #[test]
+    fn test_unicode_alias_round_trip() {
+        let app = App {
+            store: SqliteStore::open_in_memory().unwrap(),
+            redirects: HttpRedirects::new(SqliteStore::open_in_memory().unwrap()),
+            config: Config::from_vars(|name| (name == "ALIAS_ALLOW_UNICODE").then(|| "true".to_string())),
+        };
+        let send = |method: &str, path: &str, req: ApiRequest| {
+            block_on(route(&app, method, path, &req)).unwrap_or_else(|e| (e.into(), None))
+        };
+
+        // Created with a decomposed é, requested percent-encoded in either form
+        let req = ApiRequest::new().with_body(r#"{"url": "https://example.com", "custom_alias": "Cafe\u0301"}"#);
+        let (response, created) = send("POST", "/api/v1/shorten", req);
+        assert_eq!(created.expect(&response.body).short_code, "caf\u{e9}");
+
+        for path in ["/caf%C3%A9", "/cafe%CC%81", "/CAF%C3%89"] {
+            assert_eq!(send("GET", path, ApiRequest::new()).0.status, 302, "{}", path);
+        }
+        let (response, _) = send("GET", "/api/v1/alias/caf%C3%A9/available", ApiRequest::new());
+        let availability: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(availability["available"], false);
+        let (response, _) = send("GET", "/api/v1/analytics/caf%C3%A9", ApiRequest::new());
+        assert_eq!(response.status, 200, "{}", response.body);
+    }
+
+    #[test]
+    fn test_documented_paths_are_routed() {
+        let app = App {
+            store: SqliteStore::open_in_memory().unwrap(),
+            redirects: HttpRedirects::new(SqliteStore::open_in_memory().unwrap()),
+            config: Config::from_vars(|_| None),
+        };
+        let spec = crate::openapi::document("");
+
+        for (path, methods) in spec["paths"].as_object().unwrap() {
+            for method in methods.as_object().unwrap().keys() {
+                assert!(ROUTES.contains(&(method.to_uppercase().as_str(), path.as_str())), "{} {} is documented but not routed", method, path);
+            }
+        }
+
+        // Every route answers, and is documented unless it's admin-only or not part of the API
+        let undocumented = ["/api/v1/openapi.json", "/api/v1/docs", "/robots.txt", "/favicon.ico", "/"];
+        for &(method, path) in ROUTES {
+            let concrete = path.replace(['{', '}'], "");
+            let result = block_on(route(&app, method, &concrete, &ApiRequest::new()));
+            assert!(!matches!(result, Err(ApiError::NotFound)), "{} {} is listed but not handled", method, path);
+            if !undocumented.contains(&path) && !path.starts_with("/api/v1/admin/") {
+                let operation = &spec["paths"][path][method.to_lowercase()];
+                assert!(operation.is_object(), "{} {} is routed but not documented", method, path);
+            }
+        }
+        assert!(matches!(block_on(route(&app, "GET", "/api/v1/nope", &ApiRequest::new())), Err(ApiError::NotFound)));
+
+        let (response, _) = block_on(route(&app, "GET", "/api/v1/openapi.json", &ApiRequest::new())).unwrap();
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+
+use worker::kv::{KvError, KvStore};
+use worker::wasm_bindgen::JsValue;
+use worker::{D1Database, Env};
+
+use super::{ApiKeyStore, BlocklistStore, ClickStats, ClickStore, GroupCount, LinkStore, MaintenanceStore, SessionStore, StoreError, StoreResult, UserStore};
+use crate::blocklist::{BlockEntry, BlocklistCache};
+use crate::health::{HealthRow, HealthUpdate};
+use crate::models::{ApiKey, Click, Session, Url, User};
+use crate::reconcile::ReconcileReport;
+use crate::schema::{self, SchemaReport, EXPECTED_COLUMNS};
+use crate::scraper::PageMetadata;
+use crate::utils::generate_uuid;
+
+/// Production store: KV serves redirects, D1 is the source of truth for ownership and analytics
+pub struct CloudflareStore {
+    kv: KvStore,
+    db: D1Database,
+}
+
+impl CloudflareStore {
+    pub fn from_env(env: &Env) -> worker::Result<Self> {
+        Ok(Self {
+            kv: env.kv("URLS")?,
+            db: env.d1("DB")?,
+        })
+    }
+
+    async fn group_counts(&self, sql: &str, short_code: &str) -> StoreResult<Vec<GroupCount>> {
+        Ok(self.db.prepare(sql)
+            .bind(&[short_code.into()])?
+            .all()
+            .await?
+            .results::<GroupCount>()?)
+    }
+
+    // The `column` of every row `sql` returns
+    async fn names(&self, sql: &str, column: &str) -> StoreResult<Vec<String>> {
+        Ok(self.db.prepare(sql)
+            .all()
+            .await?
+            .results::<serde_json::Value>()?
+            .into_iter()
+            .filter_map(|row| row[column].as_str().map(String::from))
+            .collect())
+    }
+}
+
+impl From<worker::Error> for StoreError {
+    fn from(e: worker::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+impl From<KvError> for StoreError {
+    fn from(e: KvError) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+impl From<StoreError> for worker::Error {
+    fn from(e: StoreError) -> Self {
+        worker::Error::RustError(e.0)
+    }
+}
+
+// D1 rejects `undefined`, so missing values must be bound as NULL
+fn nullable(value: Option<String>) -> JsValue {
+    value.map(Into::into).unwrap_or(JsValue::NULL)
+}
+
+impl LinkStore for CloudflareStore {
+    async fn get_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        match self.kv.get(code).text().await? {
+            Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn reserve_link(&self, url: &Url) -> StoreResult<bool> {
+        let result = self.db.prepare(
+            "INSERT INTO urls (id, short_code, original_url, user_id, created_at, expires_at, clicks) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&[
+            url.id.clone().into(),
+            url.short_code.clone().into(),
+            url.original_url.clone().into(),
+            url.user_id.clone().unwrap_or("anonymous".to_string()).into(),
+            url.created_at.clone().into(),
+            nullable(url.expires_at.clone()),
+            0.into(),
+        ])?
+        .run()
+        .await;
+
+        // The UNIQUE constraint on short_code makes this the authoritative check
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.to_string().contains("UNIQUE constraint failed") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn publish_link(&self, url: &Url) -> StoreResult<()> {
+        self.kv.put(&url.short_code, serde_json::to_string(url)?)?.execute().await?;
+        Ok(())
+    }
+
+    async fn release_link(&self, url: &Url) -> StoreResult<()> {
+        self.db.prepare("DELETE FROM urls WHERE id = ?")
+            .bind(&[url.id.clone().into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn update_metadata(&self, short_code: &str, meta: &PageMetadata) -> StoreResult<()> {
+        // Republish the row as it is after the update, not the link as it was created,
+        // so an edit made during the scrape isn't overwritten
+        let row = self.db.prepare("UPDATE urls SET title = ?, description = ?, favicon_url = ? WHERE short_code = ? RETURNING *")
+            .bind(&[
+                nullable(meta.title.clone()),
+                nullable(meta.description.clone()),
+                nullable(meta.favicon_url.clone()),
+                short_code.into(),
+            ])?
+            .first::<Url>(None)
+            .await?;
+
+        match row {
+            Some(url) => self.publish_link(&url).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn update_link(&self, url: &Url) -> StoreResult<()> {
+        self.publish_link(url).await?;
+
+        self.db.prepare("UPDATE urls SET original_url = ?, expires_at = ? WHERE short_code = ?")
+            .bind(&[
+                url.original_url.clone().into(),
+                nullable(url.expires_at.clone()),
+                url.short_code.clone().into(),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_link(&self, url: &Url) -> StoreResult<()> {
+        // Stop redirects first, so a failure below leaves at worst a dead dashboard row
+        self.kv.delete(&url.short_code).await?;
+
+        self.db.batch(vec![
+            self.db.prepare("DELETE FROM clicks WHERE short_code = ?").bind(&[url.short_code.clone().into()])?,
+            self.db.prepare("DELETE FROM urls WHERE short_code = ?").bind(&[url.short_code.clone().into()])?,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn list_links(&self, user_id: &str, limit: u32) -> StoreResult<Vec<Url>> {
+        Ok(self.db
+            .prepare("SELECT * FROM urls WHERE user_id = ? ORDER BY created_at DESC LIMIT ?")
+            .bind(&[user_id.into(), limit.into()])?
+            .all()
+            .await?
+            .results::<Url>()?)
+    }
+
+    async fn reserve_sequence(&self, count: u32) -> StoreResult<u64> {
+        let end = self.db.prepare(
+            "INSERT INTO sequences (name, value) VALUES ('short_code', ?) ON CONFLICT(name) DO UPDATE SET value = value + excluded.value RETURNING value"
+        )
+        .bind(&[count.into()])?
+        .first::<serde_json::Value>(Some("value"))
+        .await?
+        .and_then(|v| v.as_u64().or_else(|| v.as_f64().map(|f| f as u64)))
+        .ok_or_else(|| StoreError("Sequence allocation failed".to_string()))?;
+
+        Ok(end - count as u64)
+    }
+}
+
+// The blocklist, kept for the lifetime of the isolate
+static BLOCKLIST: BlocklistCache = BlocklistCache::new();
+
+impl BlocklistStore for CloudflareStore {
+    async fn blocklist(&self) -> StoreResult<Vec<BlockEntry>> {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(entries) = BLOCKLIST.get(now) {
+            return Ok(entries);
+        }
+
+        let entries = self.db.prepare("SELECT * FROM blocklist")
+            .all()
+            .await?
+            .results::<BlockEntry>()?;
+        BLOCKLIST.set(now, entries.clone());
+        Ok(entries)
+    }
+
+    async fn add_block_entry(&self, entry: &BlockEntry) -> StoreResult<bool> {
+        let result = self.db.prepare("INSERT OR IGNORE INTO blocklist (id, kind, value, reason, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&[
+                entry.id.clone().into(),
+                entry.kind.as_str().into(),
+                entry.value.clone().into(),
+                nullable(entry.reason.clone()),
+                entry.created_at.clone().into(),
+            ])?
+            .run()
+            .await?;
+        BLOCKLIST.invalidate();
+        Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+    }
+
+    async fn remove_block_entry(&self, id: &str) -> StoreResult<bool> {
+        let result = self.db.prepare("DELETE FROM blocklist WHERE id = ?")
+            .bind(&[id.into()])?
+            .run()
+            .await?;
+        BLOCKLIST.invalidate();
+        Ok(result.meta()?.and_then(|meta| meta.changes).unwrap_or(0) > 0)
+    }
+}
+
+impl ClickStore for CloudflareStore {
+    async fn record_click(&self, click: &Click) -> StoreResult<()> {
+        self.db.prepare(
+            "INSERT INTO clicks (id, short_code, clicked_at, country, city, device_type, browser, os, referrer, ip_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&[
+            click.id.clone().into(),
+            click.short_code.clone().into(),
+            click.clicked_at.clone().into(),
+            nullable(click.country.clone()),
+            nullable(click.city.clone()),
+            nullable(click.device_type.clone()),
+            nullable(click.browser.clone()),
+            nullable(click.os.clone()),
+            nullable(click.referrer.clone()),
+            nullable(click.ip_hash.clone()),
+        ])?
+        .run()
+        .await?;
+
+        self.db.prepare("UPDATE urls SET clicks = clicks + 1 WHERE short_code = ?")
+            .bind(&[click.short_code.clone().into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn click_stats(&self, short_code: &str) -> StoreResult<ClickStats> {
+        let total_clicks = self.db.prepare("SELECT COUNT(*) AS total FROM clicks WHERE short_code = ?")
+            .bind(&[short_code.into()])?
+            .first::<i64>(Some("total"))
+            .await?
+            .unwrap_or(0);
+
+        Ok(ClickStats {
+            total_clicks,
+            countries: self.group_counts(
+                "SELECT country AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY country ORDER BY count DESC LIMIT 10",
+                short_code,
+            ).await?,
+            devices: self.group_counts(
+                "SELECT device_type AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY device_type",
+                short_code,
+            ).await?,
+            browsers: self.group_counts(
+                "SELECT browser AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY browser ORDER BY count DESC LIMIT 10",
+                short_code,
+            ).await?,
+            timeline: self.group_counts(
+                "SELECT DATE(clicked_at) AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? AND clicked_at >= datetime('now', '-30 days') GROUP BY DATE(clicked_at) ORDER BY label",
+                short_code,
+            ).await?,
+            referrers: self.group_counts(
+                "SELECT referrer AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY referrer ORDER BY count DESC LIMIT 10",
+                short_code,
+            ).await?,
+        })
+    }
+}
+
+impl SessionStore for CloudflareStore {
+    async fn create_session(&self, session: &Session) -> StoreResult<()> {
+        self.db.prepare("INSERT INTO sessions (id, user_id, created_at, expires_at, revoked_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&[
+                session.id.clone().into(),
+                session.user_id.clone().into(),
+                session.created_at.clone().into(),
+                session.expires_at.clone().into(),
+                nullable(session.revoked_at.clone()),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<Session>> {
+        Ok(self.db.prepare("SELECT * FROM sessions WHERE id = ?")
+            .bind(&[id.into()])?
+            .first::<Session>(None)
+            .await?)
+    }
+
+    async fn revoke_session(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        self.db.prepare("UPDATE sessions SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(&[revoked_at.into(), id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+}
+
+impl UserStore for CloudflareStore {
+    async fn record_user(&self, user: &User) -> StoreResult<()> {
+        self.db.prepare(
+            "INSERT INTO users (id, email, subscription_tier, created_at, stripe_customer_id) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET email = excluded.email",
+        )
+            .bind(&[
+                user.id.clone().into(),
+                nullable(user.email.clone()),
+                user.subscription_tier.as_str().into(),
+                user.created_at.clone().into(),
+                nullable(user.stripe_customer_id.clone()),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_user(&self, id: &str) -> StoreResult<Option<User>> {
+        Ok(self.db.prepare("SELECT * FROM users WHERE id = ?")
+            .bind(&[id.into()])?
+            .first::<User>(None)
+            .await?)
+    }
+}
+
+impl ApiKeyStore for CloudflareStore {
+    async fn create_api_key(&self, key: &ApiKey) -> StoreResult<()> {
+        self.db.prepare("INSERT INTO api_keys (id, user_id, name, key_hash, prefix, created_at, last_used_at, revoked_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&[
+                key.id.clone().into(),
+                key.user_id.clone().into(),
+                key.name.clone().into(),
+                key.key_hash.clone().into(),
+                key.prefix.clone().into(),
+                key.created_at.clone().into(),
+                nullable(key.last_used_at.clone()),
+                nullable(key.revoked_at.clone()),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> StoreResult<Option<ApiKey>> {
+        Ok(self.db.prepare("SELECT * FROM api_keys WHERE key_hash = ?")
+            .bind(&[key_hash.into()])?
+            .first::<ApiKey>(None)
+            .await?)
+    }
+
+    async fn list_api_keys(&self, user_id: &str) -> StoreResult<Vec<ApiKey>> {
+        Ok(self.db
+            .prepare("SELECT * FROM api_keys WHERE user_id = ? AND revoked_at IS NULL ORDER BY created_at DESC")
+            .bind(&[user_id.into()])?
+            .all()
+            .await?
+            .results::<ApiKey>()?)
+    }
+
+    async fn rename_api_key(&self, id: &str, name: &str) -> StoreResult<()> {
+        self.db.prepare("UPDATE api_keys SET name = ? WHERE id = ?")
+            .bind(&[name.into(), id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_api_key(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        self.db.prepare("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(&[revoked_at.into(), id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: &str) -> StoreResult<()> {
+        self.db.prepare("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+            .bind(&[used_at.into(), id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+}
+
+impl MaintenanceStore for CloudflareStore {
+    async fn health_batch(&self, limit: u32) -> StoreResult<Vec<HealthRow>> {
+        Ok(self.db.prepare(
+            "SELECT short_code, original_url, consecutive_failures, broken_since FROM urls ORDER BY last_checked_at IS NOT NULL, last_checked_at LIMIT ?"
+        )
+        .bind(&[limit.into()])?
+        .all()
+        .await?
+        .results::<HealthRow>()?)
+    }
+
+    async fn save_health(&self, updates: &[(String, HealthUpdate)]) -> StoreResult<()> {
+        let mut statements = Vec::with_capacity(updates.len());
+        for (code, update) in updates {
+            statements.push(
+                self.db.prepare("UPDATE urls SET last_status = ?, last_checked_at = ?, consecutive_failures = ?, broken_since = ? WHERE short_code = ?")
+                    .bind(&[
+                        update.last_status.map(Into::into).unwrap_or(JsValue::NULL),
+                        update.last_checked_at.clone().into(),
+                        update.consecutive_failures.into(),
+                        nullable(update.broken_since.clone()),
+                        code.clone().into(),
+                    ])?,
+            );
+        }
+        if !statements.is_empty() {
+            self.db.batch(statements).await?;
+        }
+        Ok(())
+    }
+
+    async fn published_codes(&self) -> StoreResult<Vec<String>> {
+        // KV lists at most 1000 keys per page
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut list = self.kv.list().limit(1000);
+            if let Some(c) = cursor.take() {
+                list = list.cursor(c);
+            }
+            let page = list.execute().await?;
+            keys.extend(page.keys.into_iter().map(|k| k.name));
+            match page.cursor {
+                Some(c) if !page.list_complete => cursor = Some(c),
+                _ => break,
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn reserved_codes(&self) -> StoreResult<Vec<String>> {
+        self.names("SELECT short_code FROM urls", "short_code").await
+    }
+
+    async fn reserved_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        Ok(self.db.prepare("SELECT * FROM urls WHERE short_code = ?")
+            .bind(&[code.into()])?
+            .first::<Url>(None)
+            .await?)
+    }
+
+    async fn save_reconcile_report(&self, report: &ReconcileReport) -> StoreResult<()> {
+        self.db.prepare("INSERT INTO reconcile_runs (id, ran_at, report) VALUES (?, ?, ?)")
+            .bind(&[
+                generate_uuid().into(),
+                report.ran_at.clone().into(),
+                serde_json::to_string(report)?.into(),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn reconcile_reports(&self, limit: u32) -> StoreResult<Vec<serde_json::Value>> {
+        Ok(self.db.prepare("SELECT report FROM reconcile_runs ORDER BY ran_at DESC LIMIT ?")
+            .bind(&[limit.into()])?
+            .all()
+            .await?
+            .results::<serde_json::Value>()?
+            .into_iter()
+            .filter_map(|row| row["report"].as_str().and_then(|r| serde_json::from_str(r).ok()))
+            .collect())
+    }
+
+    async fn schema_report(&self) -> StoreResult<SchemaReport> {
+        // Created by `wrangler d1 migrations apply`; missing on databases that were never migrated.
+        // This is a read-only report, so it is not created here.
+        let migrated = self.db.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'")
+            .first::<serde_json::Value>(None)
+            .await?
+            .is_some();
+        let applied = if migrated {
+            Some(self.names("SELECT name FROM schema_migrations ORDER BY id", "name").await?)
+        } else {
+            None
+        };
+
+        let mut columns = HashMap::new();
+        for (table, _) in EXPECTED_COLUMNS {
+            // Table names come from our own constant, never from input
+            let names = self.names(&format!("PRAGMA table_info({})", table), "name").await?;
+            columns.insert(table.to_string(), names);
+        }
+
+        Ok(schema::check(applied.as_deref(), &columns))
+    }
+}
@@ -0,0 +1,472 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::{ApiKeyStore, BlocklistStore, ClickStats, ClickStore, GroupCount, LinkStore, MaintenanceStore, SessionStore, StoreResult, UserStore};
+use crate::blocklist::BlockEntry;
+use crate::health::{HealthRow, HealthUpdate};
+use crate::models::{ApiKey, Click, Session, SubscriptionTier, Url, User};
+use crate::reconcile::ReconcileReport;
+use crate::schema::{self, SchemaReport, EXPECTED_COLUMNS, MIGRATIONS};
+use crate::scraper::PageMetadata;
+use crate::utils::current_timestamp;
+
+/// In-process store for tests and local tooling. Mirrors the KV/D1 split:
+/// reserved rows and published links are kept apart, just like D1 and KV.
+#[derive(Default)]
+pub struct MemoryStore {
+    rows: RefCell<HashMap<String, Url>>,
+    published: RefCell<HashMap<String, Url>>,
+    clicks: RefCell<Vec<Click>>,
+    blocklist: RefCell<Vec<BlockEntry>>,
+    sequence: RefCell<u64>,
+    sessions: RefCell<HashMap<String, Session>>,
+    api_keys: RefCell<Vec<ApiKey>>,
+    users: RefCell<HashMap<String, User>>,
+    reconcile_runs: RefCell<Vec<String>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserved row for `code`, whether or not it was published
+    pub fn row(&self, code: &str) -> Option<Url> {
+        self.rows.borrow().get(code).cloned()
+    }
+
+    pub fn clicks(&self) -> Vec<Click> {
+        self.clicks.borrow().clone()
+    }
+
+    /// Put a user on `tier`, creating them if needed
+    pub fn set_tier(&self, user_id: &str, tier: SubscriptionTier) {
+        let mut users = self.users.borrow_mut();
+        let user = users.entry(user_id.to_string()).or_insert_with(|| User {
+            id: user_id.to_string(),
+            email: None,
+            subscription_tier: tier,
+            created_at: current_timestamp(),
+            stripe_customer_id: None,
+        });
+        user.subscription_tier = tier;
+    }
+}
+
+impl LinkStore for MemoryStore {
+    async fn get_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        Ok(self.published.borrow().get(code).cloned())
+    }
+
+    async fn reserve_link(&self, url: &Url) -> StoreResult<bool> {
+        let mut rows = self.rows.borrow_mut();
+        if rows.contains_key(&url.short_code) {
+            return Ok(false);
+        }
+        rows.insert(url.short_code.clone(), url.clone());
+        Ok(true)
+    }
+
+    async fn publish_link(&self, url: &Url) -> StoreResult<()> {
+        self.published.borrow_mut().insert(url.short_code.clone(), url.clone());
+        Ok(())
+    }
+
+    async fn release_link(&self, url: &Url) -> StoreResult<()> {
+        let mut rows = self.rows.borrow_mut();
+        if rows.get(&url.short_code).is_some_and(|row| row.id == url.id) {
+            rows.remove(&url.short_code);
+        }
+        Ok(())
+    }
+
+    async fn update_metadata(&self, short_code: &str, meta: &PageMetadata) -> StoreResult<()> {
+        for map in [&self.rows, &self.published] {
+            if let Some(existing) = map.borrow_mut().get_mut(short_code) {
+                existing.title = meta.title.clone();
+                existing.description = meta.description.clone();
+                existing.favicon_url = meta.favicon_url.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_link(&self, url: &Url) -> StoreResult<()> {
+        for map in [&self.rows, &self.published] {
+            if let Some(existing) = map.borrow_mut().get_mut(&url.short_code) {
+                existing.original_url = url.original_url.clone();
+                existing.expires_at = url.expires_at.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_link(&self, url: &Url) -> StoreResult<()> {
+        self.rows.borrow_mut().remove(&url.short_code);
+        self.published.borrow_mut().remove(&url.short_code);
+        self.clicks.borrow_mut().retain(|c| c.short_code != url.short_code);
+        Ok(())
+    }
+
+    async fn list_links(&self, user_id: &str, limit: u32) -> StoreResult<Vec<Url>> {
+        let mut links: Vec<Url> = self
+            .rows
+            .borrow()
+            .values()
+            .filter(|url| url.user_id.as_deref() == Some(user_id))
+            .cloned()
+            .collect();
+        links.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        links.truncate(limit as usize);
+        Ok(links)
+    }
+
+    async fn reserve_sequence(&self, count: u32) -> StoreResult<u64> {
+        let mut sequence = self.sequence.borrow_mut();
+        let first = *sequence;
+        *sequence += count as u64;
+        Ok(first)
+    }
+}
+
+impl ClickStore for MemoryStore {
+    async fn record_click(&self, click: &Click) -> StoreResult<()> {
+        self.clicks.borrow_mut().push(click.clone());
+        if let Some(row) = self.rows.borrow_mut().get_mut(&click.short_code) {
+            row.clicks += 1;
+        }
+        Ok(())
+    }
+
+    async fn click_stats(&self, short_code: &str) -> StoreResult<ClickStats> {
+        let clicks: Vec<Click> = self
+            .clicks
+            .borrow()
+            .iter()
+            .filter(|c| c.short_code == short_code)
+            .cloned()
+            .collect();
+
+        let since = (chrono::Utc::now() - chrono::Duration::days(30)).to_rfc3339();
+        let recent: Vec<Click> = clicks.iter().filter(|c| c.clicked_at >= since).cloned().collect();
+
+        let mut timeline = group(&recent, |c| Some(c.clicked_at.chars().take(10).collect()));
+        timeline.sort_by(|a, b| a.label.cmp(&b.label));
+
+        Ok(ClickStats {
+            total_clicks: clicks.len() as i64,
+            countries: top(group(&clicks, |c| c.country.clone()), 10),
+            devices: group(&clicks, |c| c.device_type.clone()),
+            browsers: top(group(&clicks, |c| c.browser.clone()), 10),
+            timeline,
+            referrers: top(group(&clicks, |c| c.referrer.clone()), 10),
+        })
+    }
+}
+
+fn group(clicks: &[Click], key: impl Fn(&Click) -> Option<String>) -> Vec<GroupCount> {
+    let mut counts: HashMap<Option<String>, i64> = HashMap::new();
+    for click in clicks {
+        *counts.entry(key(click)).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(label, count)| GroupCount { label, count })
+        .collect()
+}
+
+impl BlocklistStore for MemoryStore {
+    async fn blocklist(&self) -> StoreResult<Vec<BlockEntry>> {
+        Ok(self.blocklist.borrow().clone())
+    }
+
+    async fn add_block_entry(&self, entry: &BlockEntry) -> StoreResult<bool> {
+        let mut blocklist = self.blocklist.borrow_mut();
+        if blocklist.iter().any(|e| e.kind == entry.kind && e.value == entry.value) {
+            return Ok(false);
+        }
+        blocklist.push(entry.clone());
+        Ok(true)
+    }
+
+    async fn remove_block_entry(&self, id: &str) -> StoreResult<bool> {
+        let mut blocklist = self.blocklist.borrow_mut();
+        let before = blocklist.len();
+        blocklist.retain(|e| e.id != id);
+        Ok(blocklist.len() < before)
+    }
+}
+
+impl SessionStore for MemoryStore {
+    async fn create_session(&self, session: &Session) -> StoreResult<()> {
+        self.sessions.borrow_mut().insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<Session>> {
+        Ok(self.sessions.borrow().get(id).cloned())
+    }
+
+    async fn revoke_session(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        if let Some(session) = self.sessions.borrow_mut().get_mut(id) {
+            session.revoked_at.get_or_insert_with(|| revoked_at.to_string());
+        }
+        Ok(())
+    }
+}
+
+impl UserStore for MemoryStore {
+    async fn record_user(&self, user: &User) -> StoreResult<()> {
+        let mut users = self.users.borrow_mut();
+        match users.get_mut(&user.id) {
+            Some(existing) => existing.email = user.email.clone(),
+            None => {
+                users.insert(user.id.clone(), user.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_user(&self, id: &str) -> StoreResult<Option<User>> {
+        Ok(self.users.borrow().get(id).cloned())
+    }
+}
+
+impl ApiKeyStore for MemoryStore {
+    async fn create_api_key(&self, key: &ApiKey) -> StoreResult<()> {
+        self.api_keys.borrow_mut().push(key.clone());
+        Ok(())
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> StoreResult<Option<ApiKey>> {
+        Ok(self.api_keys.borrow().iter().find(|k| k.key_hash == key_hash).cloned())
+    }
+
+    async fn list_api_keys(&self, user_id: &str) -> StoreResult<Vec<ApiKey>> {
+        let mut keys: Vec<ApiKey> = self
+            .api_keys
+            .borrow()
+            .iter()
+            .filter(|k| k.user_id == user_id && k.revoked_at.is_none())
+            .cloned()
+            .collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(keys)
+    }
+
+    async fn rename_api_key(&self, id: &str, name: &str) -> StoreResult<()> {
+        if let Some(key) = self.api_keys.borrow_mut().iter_mut().find(|k| k.id == id) {
+            key.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    async fn revoke_api_key(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        if let Some(key) = self.api_keys.borrow_mut().iter_mut().find(|k| k.id == id) {
+            key.revoked_at.get_or_insert_with(|| revoked_at.to_string());
+        }
+        Ok(())
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: &str) -> StoreResult<()> {
+        if let Some(key) = self.api_keys.borrow_mut().iter_mut().find(|k| k.id == id) {
+            key.last_used_at = Some(used_at.to_string());
+        }
+        Ok(())
+    }
+}
+
+// Most clicks first, ties broken by label so results are stable
+fn top(mut groups: Vec<GroupCount>, limit: usize) -> Vec<GroupCount> {
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.label.cmp(&b.label)));
+    groups.truncate(limit);
+    groups
+}
+
+impl MaintenanceStore for MemoryStore {
+    async fn health_batch(&self, limit: u32) -> StoreResult<Vec<HealthRow>> {
+        let mut rows: Vec<Url> = self.rows.borrow().values().cloned().collect();
+        rows.sort_by(|a, b| (a.last_checked_at.is_some(), &a.last_checked_at).cmp(&(b.last_checked_at.is_some(), &b.last_checked_at)));
+        Ok(rows
+            .into_iter()
+            .take(limit as usize)
+            .map(|url| HealthRow {
+                short_code: url.short_code,
+                original_url: url.original_url,
+                consecutive_failures: Some(url.consecutive_failures),
+                broken_since: url.broken_since,
+            })
+            .collect())
+    }
+
+    async fn save_health(&self, updates: &[(String, HealthUpdate)]) -> StoreResult<()> {
+        // Like D1, only the rows record health; published links keep what they had
+        let mut rows = self.rows.borrow_mut();
+        for (code, update) in updates {
+            if let Some(row) = rows.get_mut(code) {
+                row.last_status = update.last_status.map(i32::from);
+                row.last_checked_at = Some(update.last_checked_at.clone());
+                row.consecutive_failures = update.consecutive_failures;
+                row.broken_since = update.broken_since.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn published_codes(&self) -> StoreResult<Vec<String>> {
+        Ok(self.published.borrow().keys().cloned().collect())
+    }
+
+    async fn reserved_codes(&self) -> StoreResult<Vec<String>> {
+        Ok(self.rows.borrow().keys().cloned().collect())
+    }
+
+    async fn reserved_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        Ok(self.row(code))
+    }
+
+    async fn save_reconcile_report(&self, report: &ReconcileReport) -> StoreResult<()> {
+        self.reconcile_runs.borrow_mut().push(serde_json::to_string(report)?);
+        Ok(())
+    }
+
+    async fn reconcile_reports(&self, limit: u32) -> StoreResult<Vec<serde_json::Value>> {
+        let runs = self.reconcile_runs.borrow();
+        runs.iter().rev().take(limit as usize).map(|r| Ok(serde_json::from_str(r)?)).collect()
+    }
+
+    async fn schema_report(&self) -> StoreResult<SchemaReport> {
+        // Nothing to migrate in memory, so always current
+        let applied: Vec<String> = MIGRATIONS.iter().map(|m| m.name.to_string()).collect();
+        let columns = EXPECTED_COLUMNS
+            .iter()
+            .map(|(table, columns)| (table.to_string(), columns.iter().map(|c| c.to_string()).collect()))
+            .collect();
+        Ok(schema::check(Some(&applied), &columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reconcile::{self, ReconcileMode};
+    use crate::server::block_on;
+
+    fn link(id: &str, code: &str, user: &str, created_at: &str) -> Url {
+        Url {
+            id: id.to_string(),
+            short_code: code.to_string(),
+            original_url: "https://example.com".to_string(),
+            user_id: Some(user.to_string()),
+            created_at: created_at.to_string(),
+            expires_at: None,
+            clicks: 0,
+            title: None,
+            description: None,
+            favicon_url: None,
+            last_status: None,
+            last_checked_at: None,
+            consecutive_failures: 0,
+            broken_since: None,
+        }
+    }
+
+    fn click(code: &str, country: &str, clicked_at: &str) -> Click {
+        Click {
+            id: format!("{}-{}", code, clicked_at),
+            short_code: code.to_string(),
+            clicked_at: clicked_at.to_string(),
+            country: Some(country.to_string()),
+            city: None,
+            device_type: Some("desktop".to_string()),
+            browser: None,
+            os: None,
+            referrer: None,
+            ip_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_reserve_publish_release() {
+        let store = MemoryStore::new();
+        let first = link("1", "abc", "alice", "2024-01-01T00:00:00Z");
+
+        assert!(block_on(store.reserve_link(&first)).unwrap());
+        assert!(!block_on(store.reserve_link(&link("2", "abc", "bob", "2024-01-02T00:00:00Z"))).unwrap());
+        // Reserved but not yet published, so it doesn't redirect
+        assert!(block_on(store.get_link("abc")).unwrap().is_none());
+
+        block_on(store.publish_link(&first)).unwrap();
+        assert_eq!(block_on(store.get_link("abc")).unwrap().unwrap().id, "1");
+
+        // Only the owner of the reservation can release it
+        block_on(store.release_link(&link("2", "abc", "bob", ""))).unwrap();
+        assert!(store.row("abc").is_some());
+        block_on(store.release_link(&first)).unwrap();
+        assert!(store.row("abc").is_none());
+    }
+
+    #[test]
+    fn test_reconcile_repair() {
+        let store = MemoryStore::new();
+        let now = chrono::DateTime::parse_from_rfc3339("2030-01-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+
+        // Published without a row, and a row whose delete didn't finish; both old enough to fix
+        block_on(store.publish_link(&link("1", "kv-only", "alice", "2030-01-01T10:00:00Z"))).unwrap();
+        block_on(store.reserve_link(&link("2", "d1-only", "alice", "2030-01-01T10:00:00Z"))).unwrap();
+        // Still between reservation and publishing
+        block_on(store.reserve_link(&link("3", "fresh", "alice", "2030-01-01T11:59:00Z"))).unwrap();
+
+        let report = block_on(reconcile::run(&store, ReconcileMode::Report, now)).unwrap();
+        assert_eq!(report.drift.missing_in_d1, ["kv-only"]);
+        assert_eq!(report.drift.missing_in_kv, ["d1-only", "fresh"]);
+        assert!(report.repaired.is_empty() && report.deleted.is_empty());
+
+        let report = block_on(reconcile::run(&store, ReconcileMode::Repair, now)).unwrap();
+        assert_eq!(report.repaired, ["kv-only"]);
+        assert_eq!(report.deleted, ["d1-only"]);
+        assert_eq!(report.skipped, ["fresh"]);
+        assert!(store.row("kv-only").is_some());
+        assert!(store.row("d1-only").is_none());
+        assert!(store.row("fresh").is_some());
+
+        let reports = block_on(store.reconcile_reports(10)).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0]["deleted"][0], "d1-only");
+    }
+
+    #[test]
+    fn test_list_links_and_sequence() {
+        let store = MemoryStore::new();
+        for (id, code, user, at) in [("1", "a", "alice", "2024-01-01"), ("2", "b", "alice", "2024-01-03"), ("3", "c", "bob", "2024-01-02")] {
+            block_on(store.reserve_link(&link(id, code, user, at))).unwrap();
+        }
+
+        let codes: Vec<String> = block_on(store.list_links("alice", 50)).unwrap().into_iter().map(|u| u.short_code).collect();
+        assert_eq!(codes, ["b", "a"]);
+        assert_eq!(block_on(store.list_links("alice", 1)).unwrap().len(), 1);
+
+        assert_eq!(block_on(store.reserve_sequence(8)).unwrap(), 0);
+        assert_eq!(block_on(store.reserve_sequence(8)).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_click_stats() {
+        let store = MemoryStore::new();
+        block_on(store.reserve_link(&link("1", "abc", "alice", ""))).unwrap();
+
+        let today = chrono::Utc::now().to_rfc3339();
+        for (country, at) in [("UA", today.as_str()), ("UA", today.as_str()), ("PL", today.as_str()), ("UA", "2000-01-01T00:00:00+00:00")] {
+            block_on(store.record_click(&click("abc", country, at))).unwrap();
+        }
+        block_on(store.record_click(&click("other", "DE", &today))).unwrap();
+
+        let stats = block_on(store.click_stats("abc")).unwrap();
+        assert_eq!(stats.total_clicks, 4);
+        assert_eq!(stats.countries[0], GroupCount { label: Some("UA".to_string()), count: 3 });
+        assert_eq!(stats.countries[1], GroupCount { label: Some("PL".to_string()), count: 1 });
+        // The old click falls outside the 30 day timeline
+        assert_eq!(stats.timeline.iter().map(|g| g.count).sum::<i64>(), 3);
+        assert_eq!(store.row("abc").unwrap().clicks, 4);
+    }
+}
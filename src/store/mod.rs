@@ -0,0 +1,155 @@
+#[cfg(target_arch = "wasm32")]
+mod cloudflare;
+mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+mod sqlite;
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::blocklist::BlockEntry;
+use crate::health::{HealthRow, HealthUpdate};
+use crate::models::{ApiKey, Click, Session, Url, User};
+use crate::reconcile::ReconcileReport;
+use crate::schema::SchemaReport;
+use crate::scraper::PageMetadata;
+
+#[cfg(target_arch = "wasm32")]
+pub use cloudflare::CloudflareStore;
+pub use memory::MemoryStore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sqlite::SqliteStore;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreError(pub String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+pub type StoreResult<T> = std::result::Result<T, StoreError>;
+
+/// Number of clicks sharing one value of a grouped column; read from `SELECT <column> AS label, COUNT(*) AS count`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GroupCount {
+    pub label: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClickStats {
+    pub total_clicks: i64,
+    /// Top 10 countries, most clicks first
+    pub countries: Vec<GroupCount>,
+    pub devices: Vec<GroupCount>,
+    /// Top 10 browsers, most clicks first
+    pub browsers: Vec<GroupCount>,
+    /// Clicks per day over the last 30 days, oldest first
+    pub timeline: Vec<GroupCount>,
+    /// Top 10 referrers, most clicks first
+    pub referrers: Vec<GroupCount>,
+}
+
+/// Where links live. Creation is a two-step reserve/publish so the reservation
+/// can be atomic even when the backend that serves redirects is not.
+// Implementations are used through generics only, so the futures needn't be `Send`
+#[allow(async_fn_in_trait)]
+pub trait LinkStore {
+    /// Link that redirects under exactly `code`
+    async fn get_link(&self, code: &str) -> StoreResult<Option<Url>>;
+    /// Claim `url.short_code`; returns false if it is already taken
+    async fn reserve_link(&self, url: &Url) -> StoreResult<bool>;
+    /// Make a reserved link visible to redirects
+    async fn publish_link(&self, url: &Url) -> StoreResult<()>;
+    /// Drop a reservation whose link could not be published
+    async fn release_link(&self, url: &Url) -> StoreResult<()>;
+    /// Save the scraped title, description and favicon onto the link as it is now,
+    /// keeping changes made since it was created. A deleted link stays deleted.
+    async fn update_metadata(&self, short_code: &str, meta: &PageMetadata) -> StoreResult<()>;
+    /// Save a changed destination or expiry of a published link
+    async fn update_link(&self, url: &Url) -> StoreResult<()>;
+    /// Remove a link together with its clicks
+    async fn delete_link(&self, url: &Url) -> StoreResult<()>;
+    /// A user's links, newest first
+    async fn list_links(&self, user_id: &str, limit: u32) -> StoreResult<Vec<Url>>;
+    /// Reserve `count` consecutive values of the short code counter and return the first
+    async fn reserve_sequence(&self, count: u32) -> StoreResult<u64>;
+}
+
+#[allow(async_fn_in_trait)]
+pub trait ClickStore {
+    /// Save a click and bump the link's click counter
+    async fn record_click(&self, click: &Click) -> StoreResult<()>;
+    async fn click_stats(&self, short_code: &str) -> StoreResult<ClickStats>;
+}
+
+/// First-party sessions, so logout can revoke tokens before they expire
+#[allow(async_fn_in_trait)]
+pub trait SessionStore {
+    async fn create_session(&self, session: &Session) -> StoreResult<()>;
+    async fn get_session(&self, id: &str) -> StoreResult<Option<Session>>;
+    async fn revoke_session(&self, id: &str, revoked_at: &str) -> StoreResult<()>;
+}
+
+/// Destinations that may not be shortened or redirected to
+#[allow(async_fn_in_trait)]
+pub trait BlocklistStore {
+    /// Every entry. Read on each redirect, so implementations may cache it for `BLOCKLIST_TTL`.
+    async fn blocklist(&self) -> StoreResult<Vec<BlockEntry>>;
+    /// Returns false if the same kind and value is already blocked
+    async fn add_block_entry(&self, entry: &BlockEntry) -> StoreResult<bool>;
+    /// Returns false if there is no entry with this id
+    async fn remove_block_entry(&self, id: &str) -> StoreResult<bool>;
+}
+
+/// Signed-in users and their subscription tiers
+#[allow(async_fn_in_trait)]
+pub trait UserStore {
+    /// Insert a user, or refresh the email of an existing one; a stored tier is kept
+    async fn record_user(&self, user: &User) -> StoreResult<()>;
+    async fn get_user(&self, id: &str) -> StoreResult<Option<User>>;
+}
+
+/// API keys, looked up by the hash of the key
+#[allow(async_fn_in_trait)]
+pub trait ApiKeyStore {
+    async fn create_api_key(&self, key: &ApiKey) -> StoreResult<()>;
+    /// The key with this hash, including revoked ones
+    async fn find_api_key(&self, key_hash: &str) -> StoreResult<Option<ApiKey>>;
+    /// The user's keys that haven't been revoked, newest first
+    async fn list_api_keys(&self, user_id: &str) -> StoreResult<Vec<ApiKey>>;
+    async fn rename_api_key(&self, id: &str, name: &str) -> StoreResult<()>;
+    async fn revoke_api_key(&self, id: &str, revoked_at: &str) -> StoreResult<()>;
+    async fn touch_api_key(&self, id: &str, used_at: &str) -> StoreResult<()>;
+}
+
+/// What the scheduled jobs and the admin reports read and write
+#[allow(async_fn_in_trait)]
+pub trait MaintenanceStore {
+    /// Links checked least recently, never-checked ones first
+    async fn health_batch(&self, limit: u32) -> StoreResult<Vec<HealthRow>>;
+    /// Save the results of one health check run, keyed by short code
+    async fn save_health(&self, updates: &[(String, HealthUpdate)]) -> StoreResult<()>;
+    /// Every code visible to redirects; the KV keys on Cloudflare
+    async fn published_codes(&self) -> StoreResult<Vec<String>>;
+    /// Every reserved code; the D1 rows on Cloudflare
+    async fn reserved_codes(&self) -> StoreResult<Vec<String>>;
+    /// The reserved row for `code`, whether or not it was published
+    async fn reserved_link(&self, code: &str) -> StoreResult<Option<Url>>;
+    async fn save_reconcile_report(&self, report: &ReconcileReport) -> StoreResult<()>;
+    /// Most recent reconciliation reports, newest first
+    async fn reconcile_reports(&self, limit: u32) -> StoreResult<Vec<serde_json::Value>>;
+    /// How the database differs from the migrations and columns this build expects
+    async fn schema_report(&self) -> StoreResult<SchemaReport>;
+}
@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+
+use super::{ApiKeyStore, BlocklistStore, ClickStats, ClickStore, GroupCount, LinkStore, MaintenanceStore, SessionStore, StoreError, StoreResult, UserStore};
+use crate::blocklist::{BlockEntry, BlocklistCache};
+use crate::health::{HealthRow, HealthUpdate};
+use crate::models::{ApiKey, Click, Session, SubscriptionTier, Url, User};
+use crate::reconcile::ReconcileReport;
+use crate::scraper::PageMetadata;
+use crate::schema::{self, SchemaReport, EXPECTED_COLUMNS, MIGRATIONS, MIGRATIONS_TABLE};
+use crate::utils::generate_uuid;
+
+/// Self-hosted store: one SQLite database migrated with the same files as D1.
+/// There is no separate redirect cache, so a reserved link is immediately visible.
+pub struct SqliteStore {
+    conn: Connection,
+    blocklist: BlocklistCache,
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError(e.to_string())
+    }
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path`, apply pending migrations and
+    /// refuse to continue if the schema still doesn't match this build
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        let conn = Connection::open(path)?;
+        // The server opens a connection per thread; wait for each other's writes instead of failing
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> StoreResult<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> StoreResult<Self> {
+        let store = Self { conn, blocklist: BlocklistCache::new() };
+        store.migrate()?;
+
+        let report = store.check_schema()?;
+        if !report.is_current() {
+            return Err(StoreError(format!(
+                "Database schema does not match this build: {}",
+                serde_json::to_string(&report)?
+            )));
+        }
+        Ok(store)
+    }
+
+    /// Apply pending migrations in order, each in its own transaction
+    pub fn migrate(&self) -> StoreResult<Vec<String>> {
+        self.conn.execute_batch(MIGRATIONS_TABLE)?;
+        let applied = self.applied_migrations()?;
+
+        let mut ran = Vec::new();
+        for migration in MIGRATIONS.iter().filter(|m| !applied.iter().any(|a| a == m.name)) {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute_batch(migration.sql)
+                .map_err(|e| StoreError(format!("{}: {}", migration.name, e)))?;
+            tx.execute("INSERT INTO schema_migrations (name) VALUES (?)", [migration.name])?;
+            tx.commit()?;
+            ran.push(migration.name.to_string());
+        }
+        Ok(ran)
+    }
+
+    /// Compare the database against the migrations and columns this build expects
+    pub fn check_schema(&self) -> StoreResult<SchemaReport> {
+        let mut columns = HashMap::new();
+        for (table, _) in EXPECTED_COLUMNS {
+            // Table names come from our own constant, never from input
+            let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+            let names = stmt.query_map([], |row| row.get::<_, String>("name"))?;
+            columns.insert(table.to_string(), names.collect::<rusqlite::Result<_>>()?);
+        }
+        Ok(schema::check(Some(&self.applied_migrations()?), &columns))
+    }
+
+    fn applied_migrations(&self) -> StoreResult<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM schema_migrations ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn group_counts(&self, sql: &str, short_code: &str) -> StoreResult<Vec<GroupCount>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([short_code], |row| {
+            Ok(GroupCount {
+                label: row.get("label")?,
+                count: row.get("count")?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn url_from_row(row: &Row) -> rusqlite::Result<Url> {
+    Ok(Url {
+        id: row.get("id")?,
+        short_code: row.get("short_code")?,
+        original_url: row.get("original_url")?,
+        user_id: row.get("user_id")?,
+        created_at: row.get("created_at")?,
+        expires_at: row.get("expires_at")?,
+        clicks: row.get::<_, Option<i32>>("clicks")?.unwrap_or(0),
+        title: row.get("title")?,
+        description: row.get("description")?,
+        favicon_url: row.get("favicon_url")?,
+        last_status: row.get("last_status")?,
+        last_checked_at: row.get("last_checked_at")?,
+        consecutive_failures: row.get::<_, Option<i32>>("consecutive_failures")?.unwrap_or(0),
+        broken_since: row.get("broken_since")?,
+    })
+}
+
+fn api_key_from_row(row: &Row) -> rusqlite::Result<ApiKey> {
+    Ok(ApiKey {
+        id: row.get("id")?,
+        user_id: row.get("user_id")?,
+        name: row.get("name")?,
+        key_hash: row.get("key_hash")?,
+        prefix: row.get("prefix")?,
+        created_at: row.get("created_at")?,
+        last_used_at: row.get("last_used_at")?,
+        revoked_at: row.get("revoked_at")?,
+    })
+}
+
+impl LinkStore for SqliteStore {
+    async fn get_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        Ok(self
+            .conn
+            .query_row("SELECT * FROM urls WHERE short_code = ?", [code], url_from_row)
+            .optional()?)
+    }
+
+    async fn reserve_link(&self, url: &Url) -> StoreResult<bool> {
+        let result = self.conn.execute(
+            "INSERT INTO urls (id, short_code, original_url, user_id, created_at, expires_at, clicks) VALUES (?, ?, ?, ?, ?, ?, 0)",
+            params![
+                url.id,
+                url.short_code,
+                url.original_url,
+                url.user_id.as_deref().unwrap_or("anonymous"),
+                url.created_at,
+                url.expires_at,
+            ],
+        );
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::ConstraintViolation => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn publish_link(&self, _url: &Url) -> StoreResult<()> {
+        // The reserved row is what redirects read
+        Ok(())
+    }
+
+    async fn release_link(&self, url: &Url) -> StoreResult<()> {
+        self.conn.execute("DELETE FROM urls WHERE id = ?", [&url.id])?;
+        Ok(())
+    }
+
+    async fn update_metadata(&self, short_code: &str, meta: &PageMetadata) -> StoreResult<()> {
+        self.conn.execute(
+            "UPDATE urls SET title = ?, description = ?, favicon_url = ? WHERE short_code = ?",
+            params![meta.title, meta.description, meta.favicon_url, short_code],
+        )?;
+        Ok(())
+    }
+
+    async fn update_link(&self, url: &Url) -> StoreResult<()> {
+        self.conn.execute(
+            "UPDATE urls SET original_url = ?, expires_at = ? WHERE short_code = ?",
+            params![url.original_url, url.expires_at, url.short_code],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_link(&self, url: &Url) -> StoreResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM clicks WHERE short_code = ?", [&url.short_code])?;
+        tx.execute("DELETE FROM urls WHERE short_code = ?", [&url.short_code])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn list_links(&self, user_id: &str, limit: u32) -> StoreResult<Vec<Url>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM urls WHERE user_id = ? ORDER BY created_at DESC LIMIT ?")?;
+        let rows = stmt.query_map(params![user_id, limit], url_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn reserve_sequence(&self, count: u32) -> StoreResult<u64> {
+        let end: i64 = self.conn.query_row(
+            "INSERT INTO sequences (name, value) VALUES ('short_code', ?) ON CONFLICT(name) DO UPDATE SET value = value + excluded.value RETURNING value",
+            [count],
+            |row| row.get(0),
+        )?;
+        Ok(end as u64 - count as u64)
+    }
+}
+
+impl BlocklistStore for SqliteStore {
+    async fn blocklist(&self) -> StoreResult<Vec<BlockEntry>> {
+        let now = chrono::Utc::now().timestamp();
+        if let Some(entries) = self.blocklist.get(now) {
+            return Ok(entries);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT id, kind, value, reason, created_at FROM blocklist")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "kind": row.get::<_, String>(1)?,
+                "value": row.get::<_, String>(2)?,
+                "reason": row.get::<_, Option<String>>(3)?,
+                "created_at": row.get::<_, String>(4)?,
+            }))
+        })?;
+
+        // Same decoding as the D1 rows, so an unknown kind fails the same way
+        let entries: Vec<BlockEntry> = rows.map(|row| Ok(serde_json::from_value(row?)?)).collect::<StoreResult<_>>()?;
+        self.blocklist.set(now, entries.clone());
+        Ok(entries)
+    }
+
+    async fn add_block_entry(&self, entry: &BlockEntry) -> StoreResult<bool> {
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO blocklist (id, kind, value, reason, created_at) VALUES (?, ?, ?, ?, ?)",
+            params![entry.id, entry.kind.as_str(), entry.value, entry.reason, entry.created_at],
+        )?;
+        self.blocklist.invalidate();
+        Ok(added > 0)
+    }
+
+    async fn remove_block_entry(&self, id: &str) -> StoreResult<bool> {
+        let removed = self.conn.execute("DELETE FROM blocklist WHERE id = ?", [id])?;
+        self.blocklist.invalidate();
+        Ok(removed > 0)
+    }
+}
+
+impl ClickStore for SqliteStore {
+    async fn record_click(&self, click: &Click) -> StoreResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO clicks (id, short_code, clicked_at, country, city, device_type, browser, os, referrer, ip_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                click.id,
+                click.short_code,
+                click.clicked_at,
+                click.country,
+                click.city,
+                click.device_type,
+                click.browser,
+                click.os,
+                click.referrer,
+                click.ip_hash,
+            ],
+        )?;
+        tx.execute("UPDATE urls SET clicks = clicks + 1 WHERE short_code = ?", [&click.short_code])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn click_stats(&self, short_code: &str) -> StoreResult<ClickStats> {
+        let total_clicks = self.conn.query_row(
+            "SELECT COUNT(*) AS total FROM clicks WHERE short_code = ?",
+            [short_code],
+            |row| row.get(0),
+        )?;
+
+        Ok(ClickStats {
+            total_clicks,
+            countries: self.group_counts(
+                "SELECT country AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY country ORDER BY count DESC LIMIT 10",
+                short_code,
+            )?,
+            devices: self.group_counts(
+                "SELECT device_type AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY device_type",
+                short_code,
+            )?,
+            browsers: self.group_counts(
+                "SELECT browser AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY browser ORDER BY count DESC LIMIT 10",
+                short_code,
+            )?,
+            timeline: self.group_counts(
+                "SELECT DATE(clicked_at) AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? AND clicked_at >= datetime('now', '-30 days') GROUP BY DATE(clicked_at) ORDER BY label",
+                short_code,
+            )?,
+            referrers: self.group_counts(
+                "SELECT referrer AS label, COUNT(*) AS count FROM clicks WHERE short_code = ? GROUP BY referrer ORDER BY count DESC LIMIT 10",
+                short_code,
+            )?,
+        })
+    }
+}
+
+impl SessionStore for SqliteStore {
+    async fn create_session(&self, session: &Session) -> StoreResult<()> {
+        self.conn.execute(
+            "INSERT INTO sessions (id, user_id, created_at, expires_at, revoked_at) VALUES (?, ?, ?, ?, ?)",
+            params![session.id, session.user_id, session.created_at, session.expires_at, session.revoked_at],
+        )?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &str) -> StoreResult<Option<Session>> {
+        let session = self
+            .conn
+            .query_row("SELECT id, user_id, created_at, expires_at, revoked_at FROM sessions WHERE id = ?", [id], |row| {
+                Ok(Session {
+                    id: row.get("id")?,
+                    user_id: row.get("user_id")?,
+                    created_at: row.get("created_at")?,
+                    expires_at: row.get("expires_at")?,
+                    revoked_at: row.get("revoked_at")?,
+                })
+            })
+            .optional()?;
+        Ok(session)
+    }
+
+    async fn revoke_session(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        self.conn.execute(
+            "UPDATE sessions SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL",
+            params![revoked_at, id],
+        )?;
+        Ok(())
+    }
+}
+
+impl UserStore for SqliteStore {
+    async fn record_user(&self, user: &User) -> StoreResult<()> {
+        self.conn.execute(
+            "INSERT INTO users (id, email, subscription_tier, created_at, stripe_customer_id) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET email = excluded.email",
+            params![user.id, user.email, user.subscription_tier.as_str(), user.created_at, user.stripe_customer_id],
+        )?;
+        Ok(())
+    }
+
+    async fn get_user(&self, id: &str) -> StoreResult<Option<User>> {
+        let user = self
+            .conn
+            .query_row("SELECT * FROM users WHERE id = ?", [id], |row| {
+                let tier: String = row.get("subscription_tier")?;
+                Ok(User {
+                    id: row.get("id")?,
+                    email: row.get("email")?,
+                    // The column's CHECK constraint only admits known tiers
+                    subscription_tier: SubscriptionTier::parse(&tier).unwrap_or(SubscriptionTier::Free),
+                    created_at: row.get("created_at")?,
+                    stripe_customer_id: row.get("stripe_customer_id")?,
+                })
+            })
+            .optional()?;
+        Ok(user)
+    }
+}
+
+impl ApiKeyStore for SqliteStore {
+    async fn create_api_key(&self, key: &ApiKey) -> StoreResult<()> {
+        self.conn.execute(
+            "INSERT INTO api_keys (id, user_id, name, key_hash, prefix, created_at, last_used_at, revoked_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![key.id, key.user_id, key.name, key.key_hash, key.prefix, key.created_at, key.last_used_at, key.revoked_at],
+        )?;
+        Ok(())
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> StoreResult<Option<ApiKey>> {
+        let key = self
+            .conn
+            .query_row("SELECT * FROM api_keys WHERE key_hash = ?", [key_hash], api_key_from_row)
+            .optional()?;
+        Ok(key)
+    }
+
+    async fn list_api_keys(&self, user_id: &str) -> StoreResult<Vec<ApiKey>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM api_keys WHERE user_id = ? AND revoked_at IS NULL ORDER BY created_at DESC")?;
+        let rows = stmt.query_map([user_id], api_key_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn rename_api_key(&self, id: &str, name: &str) -> StoreResult<()> {
+        self.conn.execute("UPDATE api_keys SET name = ? WHERE id = ?", params![name, id])?;
+        Ok(())
+    }
+
+    async fn revoke_api_key(&self, id: &str, revoked_at: &str) -> StoreResult<()> {
+        self.conn.execute(
+            "UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL",
+            params![revoked_at, id],
+        )?;
+        Ok(())
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: &str) -> StoreResult<()> {
+        self.conn.execute("UPDATE api_keys SET last_used_at = ? WHERE id = ?", params![used_at, id])?;
+        Ok(())
+    }
+}
+
+impl MaintenanceStore for SqliteStore {
+    async fn health_batch(&self, limit: u32) -> StoreResult<Vec<HealthRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT short_code, original_url, consecutive_failures, broken_since FROM urls ORDER BY last_checked_at IS NOT NULL, last_checked_at LIMIT ?",
+        )?;
+        let rows = stmt.query_map([limit], |row| {
+            Ok(HealthRow {
+                short_code: row.get(0)?,
+                original_url: row.get(1)?,
+                consecutive_failures: row.get(2)?,
+                broken_since: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn save_health(&self, updates: &[(String, HealthUpdate)]) -> StoreResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (code, update) in updates {
+            tx.execute(
+                "UPDATE urls SET last_status = ?, last_checked_at = ?, consecutive_failures = ?, broken_since = ? WHERE short_code = ?",
+                params![update.last_status, update.last_checked_at, update.consecutive_failures, update.broken_since, code],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Redirects read the same table, so published and reserved codes never drift apart
+    async fn published_codes(&self) -> StoreResult<Vec<String>> {
+        self.reserved_codes().await
+    }
+
+    async fn reserved_codes(&self) -> StoreResult<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT short_code FROM urls")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    async fn reserved_link(&self, code: &str) -> StoreResult<Option<Url>> {
+        self.get_link(code).await
+    }
+
+    async fn save_reconcile_report(&self, report: &ReconcileReport) -> StoreResult<()> {
+        self.conn.execute(
+            "INSERT INTO reconcile_runs (id, ran_at, report) VALUES (?, ?, ?)",
+            params![generate_uuid(), report.ran_at, serde_json::to_string(report)?],
+        )?;
+        Ok(())
+    }
+
+    async fn reconcile_reports(&self, limit: u32) -> StoreResult<Vec<serde_json::Value>> {
+        let mut stmt = self.conn.prepare("SELECT report FROM reconcile_runs ORDER BY ran_at DESC LIMIT ?")?;
+        let rows = stmt.query_map([limit], |row| row.get::<_, String>(0))?;
+        rows.map(|report| Ok(serde_json::from_str(&report?)?)).collect()
+    }
+
+    async fn schema_report(&self) -> StoreResult<SchemaReport> {
+        self.check_schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::block_on;
+
+    fn link(id: &str, code: &str) -> Url {
+        Url {
+            id: id.to_string(),
+            short_code: code.to_string(),
+            original_url: "https://example.com".to_string(),
+            user_id: Some("alice".to_string()),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            expires_at: None,
+            clicks: 0,
+            title: None,
+            description: None,
+            favicon_url: None,
+            last_status: None,
+            last_checked_at: None,
+            consecutive_failures: 0,
+            broken_since: None,
+        }
+    }
+
+    // A database set up from schema.sql, plus the ad-hoc `migration_add_*.sql` ALTERs in `extra`:
+    // integer ids and no schema_migrations
+    fn legacy_database(extra: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        // D1 enforces foreign keys, so the rebuild must not cascade into clicks
+        conn.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        conn.execute_batch(
+            "CREATE TABLE urls (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 short_code TEXT UNIQUE NOT NULL,
+                 original_url TEXT NOT NULL,
+                 user_id TEXT NOT NULL,
+                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                 clicks INTEGER DEFAULT 0
+             );
+             CREATE TABLE clicks (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 short_code TEXT NOT NULL,
+                 clicked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                 country TEXT, city TEXT, device_type TEXT, browser TEXT, os TEXT, referrer TEXT, ip_hash TEXT,
+                 FOREIGN KEY (short_code) REFERENCES urls(short_code) ON DELETE CASCADE
+             );",
+        )
+        .unwrap();
+        conn.execute_batch(extra).unwrap();
+        conn.execute_batch(
+            "INSERT INTO urls (short_code, original_url, user_id, clicks) VALUES ('abc', 'https://example.com', 'alice', 1);
+             INSERT INTO clicks (short_code, country) VALUES ('abc', 'UA');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_upgrades_legacy_database() {
+        let store = SqliteStore::init(legacy_database("")).unwrap();
+        let url = block_on(store.get_link("abc")).unwrap().unwrap();
+        assert_eq!(url.id, "1");
+        assert_eq!(url.clicks, 1);
+        assert_eq!(url.title, None);
+        assert_eq!(url.consecutive_failures, 0);
+        assert_eq!(block_on(store.click_stats("abc")).unwrap().countries[0].label.as_deref(), Some("UA"));
+        assert_eq!(store.check_schema().unwrap().applied.len(), MIGRATIONS.len());
+
+        // Already current, so nothing runs again
+        assert!(store.migrate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_keeps_metadata_and_health() {
+        let conn = legacy_database(
+            "ALTER TABLE urls ADD COLUMN title TEXT;
+             ALTER TABLE urls ADD COLUMN description TEXT;
+             ALTER TABLE urls ADD COLUMN favicon_url TEXT;
+             ALTER TABLE urls ADD COLUMN last_status INTEGER;
+             ALTER TABLE urls ADD COLUMN last_checked_at TEXT;
+             ALTER TABLE urls ADD COLUMN consecutive_failures INTEGER DEFAULT 0;
+             ALTER TABLE urls ADD COLUMN broken_since TEXT;",
+        );
+        conn.execute_batch(
+            "UPDATE urls SET title = 'Example', favicon_url = 'https://example.com/favicon.ico',
+                             last_status = 404, consecutive_failures = 3, broken_since = '2024-01-01T00:00:00Z'",
+        )
+        .unwrap();
+
+        let store = SqliteStore::init(conn).unwrap();
+        let url = block_on(store.get_link("abc")).unwrap().unwrap();
+        assert_eq!(url.title.as_deref(), Some("Example"));
+        assert_eq!(url.description, None);
+        assert_eq!(url.favicon_url.as_deref(), Some("https://example.com/favicon.ico"));
+        assert_eq!(url.last_status, Some(404));
+        assert_eq!(url.consecutive_failures, 3);
+        assert_eq!(url.broken_since.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store.conn.execute("INSERT INTO schema_migrations (name) VALUES ('9999_future.sql')", []).unwrap();
+        assert!(SqliteStore::init(store.conn).is_err());
+    }
+
+    #[test]
+    fn test_reserve_sequence_and_delete() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert!(block_on(store.reserve_link(&link("1", "abc"))).unwrap());
+        assert!(!block_on(store.reserve_link(&link("2", "abc"))).unwrap());
+        // No separate cache: the reservation is immediately visible
+        assert_eq!(block_on(store.get_link("abc")).unwrap().unwrap().id, "1");
+
+        assert_eq!(block_on(store.reserve_sequence(8)).unwrap(), 0);
+        assert_eq!(block_on(store.reserve_sequence(8)).unwrap(), 8);
+
+        block_on(store.record_click(&Click {
+            id: "c1".to_string(),
+            short_code: "abc".to_string(),
+            clicked_at: chrono::Utc::now().to_rfc3339(),
+            country: Some("UA".to_string()),
+            city: None,
+            device_type: None,
+            browser: None,
+            os: None,
+            referrer: None,
+            ip_hash: None,
+        }))
+        .unwrap();
+        assert_eq!(block_on(store.get_link("abc")).unwrap().unwrap().clicks, 1);
+
+        block_on(store.delete_link(&link("1", "abc"))).unwrap();
+        assert!(block_on(store.get_link("abc")).unwrap().is_none());
+        assert_eq!(block_on(store.click_stats("abc")).unwrap().total_clicks, 0);
+    }
+
+    #[test]
+    fn test_session_revocation() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let session = Session {
+            id: "s1".to_string(),
+            user_id: "alice".to_string(),
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            expires_at: "2024-01-08T00:00:00+00:00".to_string(),
+            revoked_at: None,
+        };
+        block_on(store.create_session(&session)).unwrap();
+        assert_eq!(block_on(store.get_session("s1")).unwrap(), Some(session));
+
+        // The first revocation time is kept
+        block_on(store.revoke_session("s1", "2024-01-02T00:00:00+00:00")).unwrap();
+        block_on(store.revoke_session("s1", "2024-01-03T00:00:00+00:00")).unwrap();
+        let revoked = block_on(store.get_session("s1")).unwrap().unwrap();
+        assert_eq!(revoked.revoked_at.as_deref(), Some("2024-01-02T00:00:00+00:00"));
+        assert!(block_on(store.get_session("missing")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let key = |id: &str, hash: &str, created_at: &str| ApiKey {
+            id: id.to_string(),
+            user_id: "alice".to_string(),
+            name: "CI".to_string(),
+            key_hash: hash.to_string(),
+            prefix: "sk_1234abcd".to_string(),
+            created_at: created_at.to_string(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        block_on(store.create_api_key(&key("k1", "h1", "2024-01-01T00:00:00+00:00"))).unwrap();
+        block_on(store.create_api_key(&key("k2", "h2", "2024-01-02T00:00:00+00:00"))).unwrap();
+        // Hashes are unique
+        assert!(block_on(store.create_api_key(&key("k3", "h1", "2024-01-03T00:00:00+00:00"))).is_err());
+
+        block_on(store.rename_api_key("k1", "Deploy")).unwrap();
+        block_on(store.touch_api_key("k1", "2024-01-05T00:00:00+00:00")).unwrap();
+        let found = block_on(store.find_api_key("h1")).unwrap().unwrap();
+        assert_eq!(found.name, "Deploy");
+        assert_eq!(found.last_used_at.as_deref(), Some("2024-01-05T00:00:00+00:00"));
+
+        let ids = |keys: Vec<ApiKey>| keys.into_iter().map(|k| k.id).collect::<Vec<_>>();
+        assert_eq!(ids(block_on(store.list_api_keys("alice")).unwrap()), ["k2", "k1"]);
+        block_on(store.revoke_api_key("k2", "2024-01-06T00:00:00+00:00")).unwrap();
+        assert_eq!(ids(block_on(store.list_api_keys("alice")).unwrap()), ["k1"]);
+        // Revoked keys can still be found, so the caller can reject them
+        assert!(block_on(store.find_api_key("h2")).unwrap().unwrap().revoked_at.is_some());
+    }
+
+    #[test]
+    fn test_record_user_keeps_tier() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let user = |email: &str| User {
+            id: "alice".to_string(),
+            email: Some(email.to_string()),
+            subscription_tier: SubscriptionTier::Free,
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            stripe_customer_id: None,
+        };
+        block_on(store.record_user(&user("alice@example.com"))).unwrap();
+        store.conn.execute("UPDATE users SET subscription_tier = 'pro' WHERE id = 'alice'", []).unwrap();
+
+        // Signing in again refreshes the email without resetting the tier
+        block_on(store.record_user(&user("alice@example.org"))).unwrap();
+        let stored = block_on(store.get_user("alice")).unwrap().unwrap();
+        assert_eq!(stored.email.as_deref(), Some("alice@example.org"));
+        assert_eq!(stored.subscription_tier, SubscriptionTier::Pro);
+        assert!(block_on(store.get_user("bob")).unwrap().is_none());
+    }
+}